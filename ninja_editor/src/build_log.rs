@@ -0,0 +1,382 @@
+use crate::{Data, EdgeKey};
+use fs_err as fs;
+use std::{collections::HashMap, fmt, io, path::Path};
+
+/// The `.ninja_log` file ninja keeps next to the manifest, with the timings
+/// and command hashes of the outputs it built.
+///
+/// Versions 5 and 6 are supported; both are a header line followed by one
+/// line per built output:
+///
+/// ```text
+/// # ninja log v5
+/// <start ms>\t<end ms>\t<mtime>\t<output>\t<command hash>
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildLog {
+    pub version: u32,
+    /// In the order of the file; for an output, the last entry counts.
+    pub entries: Vec<LogEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub mtime: i64,
+    pub output: String,
+    /// See [`BuildLog::hash_command`].
+    pub command_hash: u64,
+}
+
+#[derive(Debug)]
+pub enum BuildLogError {
+    Io(io::Error),
+    Malformed(String),
+}
+impl fmt::Display for BuildLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildLogError::Io(e) => write!(f, "{e}"),
+            BuildLogError::Malformed(e) => write!(f, "malformed build log: {e}"),
+        }
+    }
+}
+impl std::error::Error for BuildLogError {}
+impl From<io::Error> for BuildLogError {
+    fn from(e: io::Error) -> Self {
+        BuildLogError::Io(e)
+    }
+}
+
+const HEADER: &str = "# ninja log v";
+
+impl BuildLog {
+    pub fn new(version: u32) -> BuildLog {
+        BuildLog {
+            version,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<BuildLog, BuildLogError> {
+        let malformed = |n: usize, message: &str| {
+            BuildLogError::Malformed(format!("line {}: {}", n + 1, message))
+        };
+        let mut lines = text.lines().enumerate();
+
+        let version = lines
+            .next()
+            .and_then(|(_, x)| x.strip_prefix(HEADER))
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| malformed(0, "expected `# ninja log v<version>`"))?;
+        if !matches!(version, 5 | 6) {
+            return Err(malformed(0, &format!("unsupported version {}", version)));
+        }
+
+        let mut log = BuildLog::new(version);
+        for (n, line) in lines {
+            let fields: Vec<_> = line.split('\t').collect();
+            let [start, end, mtime, output, hash] = fields[..] else {
+                return Err(malformed(n, "expected 5 fields separated by tabs"));
+            };
+            let number = |x: &str| x.parse().map_err(|_| malformed(n, "invalid number"));
+            log.entries.push(LogEntry {
+                start_ms: number(start)?,
+                end_ms: number(end)?,
+                mtime: mtime.parse().map_err(|_| malformed(n, "invalid mtime"))?,
+                output: output.to_string(),
+                command_hash: u64::from_str_radix(hash, 16)
+                    .map_err(|_| malformed(n, "invalid command hash"))?,
+            });
+        }
+        Ok(log)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<BuildLog, BuildLogError> {
+        BuildLog::parse(&fs::read_to_string(path.as_ref())?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path.as_ref(), self.to_string())
+    }
+
+    /// The entry ninja uses for `output`.
+    pub fn entry(&self, output: &str) -> Option<&LogEntry> {
+        self.entries.iter().rev().find(|x| x.output == output)
+    }
+
+    /// Renames the entries of `from`; returns how many there were.
+    pub fn rename(&mut self, from: &str, to: &str) -> usize {
+        let mut count = 0;
+        for i in self.entries.iter_mut().filter(|x| x.output == from) {
+            i.output = to.to_string();
+            count += 1;
+        }
+        count
+    }
+
+    /// Replaces `from` with `to` at the start of every output starting with
+    /// `from`; returns how many entries changed.
+    pub fn rename_prefix(&mut self, from: &str, to: &str) -> usize {
+        let mut count = 0;
+        for i in &mut self.entries {
+            if let Some(rest) = i.output.strip_prefix(from) {
+                i.output = format!("{}{}", to, rest);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// The hash ninja logs for a command: MurmurHash64A, with the response
+    /// file content appended as `;rspfile=<content>` when there is one.
+    pub fn hash_command(command: &str) -> u64 {
+        const SEED: u64 = 0xDECA_FBAD_DECA_FBAD;
+        const M: u64 = 0xc6a4_a793_5bd1_e995;
+        const R: u32 = 47;
+
+        let data = command.as_bytes();
+        let mut h = SEED ^ (data.len() as u64).wrapping_mul(M);
+        let mut chunks = data.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+            k = k.wrapping_mul(M);
+            k ^= k >> R;
+            k = k.wrapping_mul(M);
+            h ^= k;
+            h = h.wrapping_mul(M);
+        }
+        let rest = chunks.remainder();
+        if !rest.is_empty() {
+            for (n, &byte) in rest.iter().enumerate() {
+                h ^= u64::from(byte) << (8 * n);
+            }
+            h = h.wrapping_mul(M);
+        }
+        h ^= h >> R;
+        h = h.wrapping_mul(M);
+        h ^= h >> R;
+        h
+    }
+}
+
+impl fmt::Display for BuildLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}{}", HEADER, self.version)?;
+        for i in &self.entries {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{:x}",
+                i.start_ms, i.end_ms, i.mtime, i.output, i.command_hash
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Data<'_> {
+    /// The hash of the command of `edge` as ninja logs it, see
+    /// [`BuildLog::hash_command`].
+    pub fn command_hash(&self, edge: EdgeKey) -> u64 {
+        let mut command = self.evaluate_edge(edge, "command");
+        let rspfile_content = self.evaluate_edge(edge, "rspfile_content");
+        if !rspfile_content.is_empty() {
+            command += ";rspfile=";
+            command += &rspfile_content;
+        }
+        BuildLog::hash_command(&command)
+    }
+}
+
+/// Moves the entries of renamed outputs to their new paths. Entries that were
+/// up to date with the command of their edge get the hash of the new command,
+/// so renaming alone doesn't make ninja rebuild them.
+pub(crate) fn rename_entries(
+    log: &mut BuildLog,
+    renames: &HashMap<&str, &str>,
+    old: &Data,
+    new: &Data,
+) {
+    let mut hashes: HashMap<(bool, EdgeKey), u64> = HashMap::new();
+    let mut hash = |data: &Data, is_new: bool, output: &str| {
+        let edge = data.nodes[data.node(output)?].in_edge?;
+        Some(
+            *hashes
+                .entry((is_new, edge))
+                .or_insert_with(|| data.command_hash(edge)),
+        )
+    };
+
+    for entry in &mut log.entries {
+        let output = renames
+            .get(entry.output.as_str())
+            .map_or(entry.output.clone(), |x| x.to_string());
+        let old_hash = hash(old, false, &entry.output);
+        let new_hash = hash(new, true, &output);
+        if let (Some(old_hash), Some(new_hash)) = (old_hash, new_hash) {
+            if old_hash == entry.command_hash {
+                entry.command_hash = new_hash;
+            }
+        }
+        entry.output = output;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ninja;
+
+    const LOG: &str = "# ninja log v5\n\
+                       10\t250\t1700000000123456789\tout/a.o\t5c3e8a1f0b2d4e6a\n\
+                       12\t300\t-1\tout/b c.o\t0\n";
+
+    #[test]
+    fn versions_and_line_breaks() {
+        let log = BuildLog::parse(LOG).unwrap();
+        assert_eq!(
+            log.entries[0],
+            LogEntry {
+                start_ms: 10,
+                end_ms: 250,
+                mtime: 1700000000123456789,
+                output: "out/a.o".to_string(),
+                command_hash: 0x5c3e8a1f0b2d4e6a,
+            }
+        );
+        // Paths are written as they are, spaces and all.
+        assert_eq!(log.entries[1].output, "out/b c.o");
+        assert_eq!(log.to_string(), LOG);
+
+        let v6 = LOG.replace("v5", "v6");
+        assert_eq!(BuildLog::parse(&v6).unwrap().version, 6);
+        // Windows builds of ninja write CRLF line breaks.
+        let crlf = BuildLog::parse(&LOG.replace('\n', "\r\n")).unwrap();
+        assert_eq!(crlf, log);
+
+        for version in ["4", "7", "x", ""] {
+            let text = format!("# ninja log v{version}\n");
+            assert!(BuildLog::parse(&text).is_err(), "{text}");
+        }
+        assert_eq!(
+            BuildLog::parse("# ninja log v4\n").unwrap_err().to_string(),
+            "malformed build log: line 1: unsupported version 4"
+        );
+    }
+
+    #[test]
+    fn last_entry_counts() {
+        let text = "# ninja log v6\n\
+                    0\t1\t2\ta.o\t1\n\
+                    0\t1\t2\tb.o\t3\n\
+                    5\t6\t7\ta.o\t2\n";
+        let log = BuildLog::parse(text).unwrap();
+        assert_eq!(log.entry("a.o").unwrap().command_hash, 2);
+        assert_eq!(log.entry("b.o").unwrap().command_hash, 3);
+        assert_eq!(log.entry("c.o"), None);
+        // Older entries are kept, as ninja leaves them until it recompacts.
+        assert_eq!(log.entries.len(), 3);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let message = |text: &str| match BuildLog::parse(text) {
+            Err(BuildLogError::Malformed(e)) => e,
+            x => panic!("{x:?}"),
+        };
+        assert_eq!(
+            message("# ninja log v5\n0\t1\t2\ta.o\n"),
+            "line 2: expected 5 fields separated by tabs"
+        );
+        assert_eq!(
+            message("# ninja log v5\n0\t1\t2\ta.o\t1\n-3\t1\t2\tb.o\t1\n"),
+            "line 3: invalid number"
+        );
+        assert_eq!(
+            message("# ninja log v5\n0\t1\tx\ta.o\t1\n"),
+            "line 2: invalid mtime"
+        );
+        assert_eq!(
+            message("# ninja log v5\n0\t1\t2\ta.o\t12g\n"),
+            "line 2: invalid command hash"
+        );
+        // A log cut in the middle of a line, as after a crash while ninja
+        // was writing it.
+        let truncated = &LOG[..LOG.len() - 4];
+        assert_eq!(
+            message(truncated),
+            "line 3: expected 5 fields separated by tabs"
+        );
+        // Cut right after a line break, the entries before it are complete.
+        let len = LOG.find('\n').unwrap() + 1;
+        assert!(BuildLog::parse(&LOG[..len]).unwrap().entries.is_empty());
+    }
+
+    #[test]
+    fn renaming_rehashes_up_to_date_entries() {
+        let dir =
+            std::env::temp_dir().join(format!("ninja_editor_build_log_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("build.ninja");
+        fs::write(
+            &manifest,
+            "rule link\n  command = ld @$out.rsp -o $out\n  rspfile = $out.rsp\n  \
+             rspfile_content = $in\n\
+             build a.out: link a.o\n\
+             build b.out: link b.o\n",
+        )
+        .unwrap();
+        let ninja = Ninja::load(&manifest);
+        let data = ninja.data();
+        let edge = |data: &Data, output| data.nodes[data.node(output).unwrap()].in_edge.unwrap();
+
+        // The response file content is part of the hash.
+        assert_eq!(
+            data.command_hash(edge(data, "a.out")),
+            BuildLog::hash_command("ld @a.out.rsp -o a.out;rspfile=a.o")
+        );
+
+        let path = dir.join(".ninja_log");
+        let mut log = BuildLog::new(5);
+        for (output, command_hash) in [
+            ("a.out", data.command_hash(edge(data, "a.out"))),
+            ("b.out", 1),
+            ("c.out", 2),
+        ] {
+            log.entries.push(LogEntry {
+                start_ms: 0,
+                end_ms: 1,
+                mtime: 2,
+                output: output.to_string(),
+                command_hash,
+            });
+        }
+        log.save(&path).unwrap();
+
+        let mut changes = ninja.change();
+        changes.set_build_log(&path);
+        changes
+            .rename_node(data.node("a.out").unwrap(), "bin/a")
+            .unwrap();
+        changes
+            .rename_node(data.node("b.out").unwrap(), "bin/b")
+            .unwrap();
+        changes.commit().unwrap();
+
+        let renamed = Ninja::load(&manifest);
+        let log = BuildLog::load(&path).unwrap();
+        let outputs: Vec<_> = log.entries.iter().map(|x| x.output.as_str()).collect();
+        assert_eq!(outputs, ["bin/a", "bin/b", "c.out"]);
+        // `a.out` was up to date, so it stays so under its new command.
+        let hash = renamed.data().command_hash(edge(renamed.data(), "bin/a"));
+        assert_eq!(
+            hash,
+            BuildLog::hash_command("ld @bin/a.rsp -o bin/a;rspfile=a.o")
+        );
+        assert_eq!(log.entries[0].command_hash, hash);
+        // `b.out` was out of date and stays so.
+        assert_eq!(log.entries[1].command_hash, 1);
+        assert_eq!(log.entries[2].command_hash, 2);
+    }
+}
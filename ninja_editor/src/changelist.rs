@@ -1,20 +1,36 @@
 use filetime::{set_file_mtime, FileTime};
 
-use crate::{lexer::Location, Ninja, RuleKey, Source, SourceId};
-use std::{collections::HashMap, fs};
-
+use crate::{
+    build_log::{rename_entries, BuildLog},
+    deps_log::{DepsLog, DepsLogError},
+    format::FormatOptions,
+    journal::Journal,
+    lexer::Location,
+    validate::{graph_differences, validate, ValidationError},
+    Diagnostic, EdgeKey, EscapeError, EvalString, FileKey, Ninja, NodeKey, PathKind, RuleKey,
+    Source, SourceId, SourceManager, SyntaxTree,
+};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
+#[derive(Clone)]
 struct ChangeRaw<'x> {
     loc: Location,
-    new_text: &'x str,
+    new_text: Cow<'x, str>,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct ChangesRaw<'x> {
     files: HashMap<SourceId, Vec<ChangeRaw<'x>>>,
 }
 impl<'x> ChangesRaw<'x> {
-    fn add_change(&mut self, loc: Location, new_text: &'x str) {
+    fn add_change<T: Into<Cow<'x, str>>>(&mut self, loc: Location, new_text: T) {
+        let new_text = new_text.into();
         self.files
             .entry(loc.source_id)
             .or_default()
@@ -22,61 +38,601 @@ impl<'x> ChangesRaw<'x> {
     }
 }
 
+/// The new text of a changed source, with the ranges the changes ended up at.
+pub(crate) struct GeneratedFile {
+    pub(crate) text: String,
+    /// Original location of each change and the range of its replacement text.
+    pub(crate) changed: Vec<(Location, Range<usize>)>,
+}
+
+/// Why [`ChangeList::rewrite_paths`] renamed nothing.
+#[cfg(feature = "regex")]
+#[derive(Debug)]
+pub enum RewriteError {
+    Escape(EscapeError),
+    /// Nodes would end up with the same path, `path`; `nodes` are their
+    /// current paths, including the one of a node already there.
+    Collision {
+        path: String,
+        nodes: Vec<String>,
+    },
+}
+#[cfg(feature = "regex")]
+impl std::fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RewriteError::Escape(e) => write!(f, "{e}"),
+            RewriteError::Collision { path, nodes } => {
+                let nodes: Vec<_> = nodes.iter().map(|x| format!("`{x}`")).collect();
+                write!(f, "{} would share the path `{}`", nodes.join(", "), path)
+            }
+        }
+    }
+}
+#[cfg(feature = "regex")]
+impl std::error::Error for RewriteError {}
+#[cfg(feature = "regex")]
+impl From<EscapeError> for RewriteError {
+    fn from(e: EscapeError) -> Self {
+        RewriteError::Escape(e)
+    }
+}
+
+#[derive(Clone)]
 pub struct ChangeList<'x> {
     ninja: &'x Ninja,
     changes: ChangesRaw<'x>,
+    validate: bool,
+    journal: Option<PathBuf>,
+    build_log: Option<PathBuf>,
+    deps_log: Option<PathBuf>,
+    /// Nodes renamed with [`ChangeList::rename_node`], with their new paths.
+    renames: Vec<(NodeKey, String)>,
+    /// The prefixes passed to [`ChangeList::rename_prefix`].
+    prefix_renames: Vec<(String, String)>,
+    /// Paths given to [`ChangeList::add_edge_path`], escaped.
+    pub(crate) added_paths: Vec<(EdgeKey, PathKind, String)>,
+    /// Text to add at the end of files.
+    pub(crate) appended: Vec<(FileKey, String)>,
 }
 impl<'x> ChangeList<'x> {
-    pub(crate) fn new(ninja: &Ninja) -> ChangeList {
+    pub(crate) fn new(ninja: &Ninja) -> ChangeList<'_> {
         ChangeList {
             ninja,
             changes: ChangesRaw::default(),
+            validate: false,
+            journal: None,
+            build_log: None,
+            deps_log: None,
+            renames: Vec::new(),
+            prefix_renames: Vec::new(),
+            added_paths: Vec::new(),
+            appended: Vec::new(),
         }
     }
 
-    pub fn rename_rule(&mut self, rule_key: RuleKey, new_name: &'x str) {
+    pub fn rename_rule<T: Into<Cow<'x, str>>>(&mut self, rule_key: RuleKey, new_name: T) {
+        let new_name = new_name.into();
         let rule = &self.ninja.data.rules[rule_key];
-        self.changes.add_change(rule.name.loc, new_name);
-    
-        for i in self.ninja.data.edges.values().filter(|x| rule_key == x.rule) {
-            self.changes.add_change(i.rule_loc, new_name);
+        self.changes.add_change(rule.name.loc, new_name.clone());
+
+        for i in self
+            .ninja
+            .data
+            .edges
+            .values()
+            .filter(|x| rule_key == x.rule)
+        {
+            self.changes.add_change(i.rule_loc, new_name.clone());
         }
     }
 
+    /// Replaces the node's path everywhere it's written, including where it
+    /// was written with variables.
+    pub fn rename_node(&mut self, node: NodeKey, new_path: &str) -> Result<(), EscapeError> {
+        let text = EvalString::literal(new_path).to_path()?;
+        for &loc in &self.ninja.data.nodes[node].locs {
+            self.changes.add_change(loc, text.clone());
+        }
+        self.renames.push((node, new_path.to_string()));
+        Ok(())
+    }
+
+    /// Renames every node whose path starts with `from` to start with `to`
+    /// instead; returns how many were renamed.
+    pub fn rename_prefix(&mut self, from: &str, to: &str) -> Result<usize, EscapeError> {
+        let nodes: Vec<_> = self
+            .ninja
+            .data
+            .nodes
+            .iter()
+            .filter_map(|(key, x)| Some((key, x.path.strip_prefix(from)?)))
+            .collect();
+        for &(key, rest) in &nodes {
+            self.rename_node(key, &format!("{}{}", to, rest))?;
+        }
+        self.prefix_renames.push((from.to_string(), to.to_string()));
+        Ok(nodes.len())
+    }
+
+    /// Renames every node whose path matches `regex`, replacing the first
+    /// match with `replacement`, in which `$1` or `${name}` is the text of a
+    /// group as with [`regex::Regex::replace`]. Paths are matched as ninja
+    /// sees them, without the `$ ` and `$:` escapes; the new paths are
+    /// escaped again. Returns how many places were rewritten in each manifest
+    /// file, in the order they were loaded, leaving out the files without
+    /// any.
+    ///
+    /// Nothing is renamed if a new path can't be escaped, or if two nodes
+    /// would end up with the same path, whether both are renamed or one
+    /// keeps its path.
+    #[cfg(feature = "regex")]
+    pub fn rewrite_paths(
+        &mut self,
+        regex: &regex::Regex,
+        replacement: &str,
+    ) -> Result<Vec<(PathBuf, usize)>, RewriteError> {
+        let ninja = self.ninja;
+        let nodes: Vec<_> = ninja
+            .data
+            .nodes
+            .iter()
+            .filter_map(|(key, x)| {
+                let new_path = regex.replace(&x.path, replacement);
+                (new_path != x.path).then(|| (key, new_path.into_owned()))
+            })
+            .collect();
+        // Checked before renaming anything, like the collisions.
+        for (_, new_path) in &nodes {
+            EvalString::literal(new_path.as_str()).to_path()?;
+        }
+
+        // The paths the nodes have after the renames of this change list.
+        let renamed: HashMap<_, _> = self
+            .renames
+            .iter()
+            .chain(&nodes)
+            .map(|(key, path)| (*key, path.as_str()))
+            .collect();
+        let mut by_path: HashMap<&str, Vec<NodeKey>> = HashMap::new();
+        for (key, node) in &ninja.data.nodes {
+            let path = renamed.get(&key).copied().unwrap_or(&node.path);
+            by_path.entry(path).or_default().push(key);
+        }
+        for (_, new_path) in &nodes {
+            let keys = &by_path[new_path.as_str()];
+            if keys.len() > 1 {
+                return Err(RewriteError::Collision {
+                    path: new_path.clone(),
+                    nodes: keys
+                        .iter()
+                        .map(|&x| ninja.data.nodes[x].path.clone())
+                        .collect(),
+                });
+            }
+        }
+
+        let mut counts = vec![0; ninja.sm.sources.len()];
+        for (key, new_path) in nodes {
+            self.rename_node(key, &new_path)?;
+            for loc in &ninja.data.nodes[key].locs {
+                counts[loc.source_id.0 as usize] += 1;
+            }
+        }
+        Ok(ninja
+            .sm
+            .sources
+            .iter()
+            .zip(counts)
+            .filter(|x| x.1 > 0)
+            .map(|(source, count)| (source.path.clone(), count))
+            .collect())
+    }
+
     pub fn change(&mut self, loc: Location, new_text: &'x str) {
         self.changes.add_change(loc, new_text);
     }
 
-    pub fn commit(self) {
-        for (source, changes) in self.changes.files {
-            let source = self.ninja.sm.get(source);
-            create_new_file(source, changes);
+    /// Like [`ChangeList::change`], for text built by the edits defined in
+    /// other modules.
+    pub(crate) fn replace(&mut self, loc: Location, new_text: String) {
+        self.changes.add_change(loc, new_text);
+    }
+
+    pub(crate) fn ninja(&self) -> &'x Ninja {
+        self.ninja
+    }
+
+    /// Reformats every manifest file, see [`SyntaxTree::format`]. Each file is
+    /// replaced as a whole, so this shouldn't be combined with other changes.
+    ///
+    /// Fails without adding anything if the formatted files don't parse to
+    /// the same graph.
+    pub fn format(&mut self, options: &FormatOptions) -> Result<(), Diagnostic> {
+        let mut files = HashMap::new();
+        for source in &self.ninja.sm.sources {
+            let tree = SyntaxTree::build(source.text(), source.id)
+                .map_err(|e| Diagnostic::from_parse_error(&self.ninja.sm, e))?;
+            let text = tree.format(options);
+            if text != source.text() {
+                let loc = Location {
+                    start: 0,
+                    stop: source.text().len(),
+                    source_id: source.id,
+                };
+                let changed = vec![(loc, 0..text.len())];
+                files.insert(source.id, GeneratedFile { text, changed });
+            }
+        }
+
+        let formatted = reparse(self.ninja, &files)?;
+        let differences = graph_differences(self.ninja, &formatted);
+        if !differences.is_empty() {
+            return Err(Diagnostic::for_file(
+                self.ninja.root_path(),
+                format!(
+                    "formatting would change the graph: {}",
+                    differences.join("; ")
+                ),
+            ));
+        }
+
+        for (_, file) in files {
+            let (loc, _) = file.changed[0];
+            self.changes.add_change(loc, file.text);
+        }
+        Ok(())
+    }
+
+    /// Re-parse the whole include tree with the changes applied before
+    /// writing anything, and refuse to commit if it no longer parses or if
+    /// nodes appear or disappear other than the ones that were edited.
+    pub fn set_validate(&mut self, validate: bool) {
+        self.validate = validate;
+    }
+
+    /// Write a [`Journal`] to `path` before touching any file, so that the
+    /// commit can be reverted later.
+    pub fn set_journal<P: Into<PathBuf>>(&mut self, path: P) {
+        self.journal = Some(path.into());
+    }
+
+    /// Move the entries of nodes renamed by this change list in the
+    /// `.ninja_log` at `path` on commit, keeping their timings, so that ninja
+    /// doesn't consider the renamed outputs out of date. The log is part of
+    /// the journal.
+    pub fn set_build_log<P: Into<PathBuf>>(&mut self, path: P) {
+        self.build_log = Some(path.into());
+    }
+
+    /// Rename the paths of renamed nodes in the `.ninja_deps` at `path` on
+    /// commit. Paths under the prefixes given to [`ChangeList::rename_prefix`]
+    /// are renamed too, whether they are nodes or not, since discovered
+    /// dependencies usually aren't in the manifest. The log is part of the
+    /// journal, like the build log.
+    pub fn set_deps_log<P: Into<PathBuf>>(&mut self, path: P) {
+        self.deps_log = Some(path.into());
+    }
+
+    /// Applies the changes in memory and parses the result, leaving the files
+    /// on disk untouched.
+    ///
+    /// The returned `Ninja` can be edited again; committing any of its change
+    /// lists writes the edits of the whole chain.
+    /// Fails if two changes replace overlapping text.
+    pub fn apply(&self) -> Result<Ninja, Diagnostic> {
+        reparse(self.ninja, &self.generate()?)
+    }
+
+    /// Fails like [`ChangeList::apply`] if two changes overlap, without
+    /// parsing anything.
+    #[cfg(feature = "recipe")]
+    pub(crate) fn check_overlaps(&self) -> Result<(), Diagnostic> {
+        self.generate().map(|_| ())
+    }
+
+    pub fn commit(self) -> Result<(), ValidationError> {
+        let files = self.generate().map_err(|e| ValidationError {
+            diagnostics: vec![e],
+        })?;
+        if self.validate {
+            validate(self.ninja, &files)?;
+        }
+
+        let mut writes: Vec<_> = files
+            .iter()
+            .map(|(&source, file)| (self.ninja.sm.get(source), file.text.as_str()))
+            .collect();
+        // Sources edited by the change lists this `Ninja` was applied from.
+        for source in &self.ninja.sm.sources {
+            if self.ninja.sm.overrides.contains_key(&source.path) && !files.contains_key(&source.id)
+            {
+                writes.push((source, source.text()));
+            }
+        }
+
+        let log = match &self.build_log {
+            Some(path) if path.exists() && !self.renames.is_empty() => {
+                let error = |e: &dyn std::fmt::Display| ValidationError {
+                    diagnostics: vec![Diagnostic::for_file(path, e.to_string())],
+                };
+                let original = fs::read_to_string(path).map_err(|e| error(&e))?;
+                let mut log = BuildLog::parse(&original).map_err(|e| error(&e))?;
+                let renamed = reparse(self.ninja, &files).map_err(|e| error(&e))?;
+                let renames = self
+                    .renames
+                    .iter()
+                    .map(|(node, path)| (self.ninja.data.nodes[*node].path.as_str(), path.as_str()))
+                    .collect();
+                rename_entries(&mut log, &renames, &self.ninja.data, &renamed.data);
+                Some((path, original, log.to_string()))
+            }
+            _ => None,
+        };
+
+        let deps_log = match &self.deps_log {
+            Some(path) if path.exists() && !self.renames.is_empty() => {
+                let error = |e: DepsLogError| ValidationError {
+                    diagnostics: vec![Diagnostic::for_file(path, e.to_string())],
+                };
+                let original = fs::read(path).map_err(|e| error(e.into()))?;
+                let mut log = DepsLog::parse(&original).map_err(error)?;
+                let renames: HashMap<_, _> = self
+                    .renames
+                    .iter()
+                    .map(|(node, path)| (self.ninja.data.nodes[*node].path.as_str(), path))
+                    .collect();
+                log.rename_with(|path| match renames.get(path) {
+                    Some(&x) => Some(x.clone()),
+                    None => self.prefix_renames.iter().find_map(|(from, to)| {
+                        Some(format!("{}{}", to, path.strip_prefix(from.as_str())?))
+                    }),
+                });
+                Some((path, original, log.to_bytes().map_err(error)?))
+            }
+            _ => None,
+        };
+
+        let error = |path: &Path, e: io::Error| ValidationError {
+            diagnostics: vec![Diagnostic::for_file(path, e.to_string())],
+        };
+        if let Some(path) = &self.journal {
+            self.write_journal(path, &files, &writes, &log, &deps_log)
+                .map_err(|e| error(path, e))?;
+        }
+        for (source, text) in writes {
+            write_file(source, text).map_err(|e| error(&source.path, e))?;
+        }
+        if let Some((path, _, text)) = log {
+            fs::write(path, text).map_err(|e| error(path, e))?;
         }
+        if let Some((path, _, bytes)) = deps_log {
+            fs::write(path, bytes).map_err(|e| error(path, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Records the original text of every file `commit` overwrites, the build
+    /// and deps logs included.
+    fn write_journal(
+        &self,
+        path: &Path,
+        files: &HashMap<SourceId, GeneratedFile>,
+        writes: &[(&Source, &str)],
+        log: &Option<(&PathBuf, String, String)>,
+        deps_log: &Option<(&PathBuf, Vec<u8>, Vec<u8>)>,
+    ) -> io::Result<()> {
+        let mut journal = Journal::default();
+        for &(source, text) in writes {
+            match files.get(&source.id) {
+                // Only the edited ranges differ from what is on disk.
+                Some(file) if !self.ninja.sm.overrides.contains_key(&source.path) => {
+                    let changes = file
+                        .changed
+                        .iter()
+                        .map(|(loc, range)| (range.clone(), source.str_loc(*loc).into()))
+                        .collect();
+                    journal.add_file(
+                        &source.path,
+                        source.text().as_bytes(),
+                        text.as_bytes(),
+                        changes,
+                    )?;
+                }
+                _ => {
+                    let original = fs::read(&source.path)?;
+                    let changes = vec![(0..text.len(), original.clone())];
+                    journal.add_file(&source.path, &original, text.as_bytes(), changes)?;
+                }
+            }
+        }
+        if let Some((log_path, original, text)) = log {
+            let changes = vec![(0..text.len(), original.clone().into_bytes())];
+            journal.add_file(log_path, original.as_bytes(), text.as_bytes(), changes)?;
+        }
+        if let Some((log_path, original, bytes)) = deps_log {
+            let changes = vec![(0..bytes.len(), original.clone())];
+            journal.add_file(log_path, original, bytes, changes)?;
+        }
+        journal.save(path)
+    }
+
+    fn generate(&self) -> Result<HashMap<SourceId, GeneratedFile>, Diagnostic> {
+        let mut changes = self.changes.clone();
+        // Before the other changes at the same place, so added paths come
+        // before added bindings.
+        for (loc, text) in self.pending_changes().into_iter().rev() {
+            let file = changes.files.entry(loc.source_id).or_default();
+            file.insert(
+                0,
+                ChangeRaw {
+                    loc,
+                    new_text: text.into(),
+                },
+            );
+        }
+        changes
+            .files
+            .iter()
+            .map(|(&source, changes)| {
+                let source_text = self.ninja.sm.get(source).text();
+                let file = generate_new_file(source_text, changes)
+                    .map_err(|loc| Diagnostic::at(&self.ninja.sm, loc, "conflicting changes"))?;
+                Ok((source, file))
+            })
+            .collect()
     }
 }
 
-fn create_new_file(source: &Source, changes: Vec<ChangeRaw>) {
-    let mtime = FileTime::from_last_modification_time(&source.path.metadata().unwrap());
+/// Parses the edited sources together with the rest of the include tree.
+pub(crate) fn reparse(
+    ninja: &Ninja,
+    files: &HashMap<SourceId, GeneratedFile>,
+) -> Result<Ninja, Diagnostic> {
+    let texts = files
+        .iter()
+        .map(|(&id, file)| (ninja.sm.get(id).path.clone(), file.text.clone()));
+    reparse_with(ninja, texts)
+}
 
-    let text = generate_new_file(source.text(), changes);
-    fs::write(&source.path, text).unwrap();
+/// Parses the manifest with the given texts in place of the files at their
+/// paths, which don't need to exist.
+pub(crate) fn reparse_with<I: IntoIterator<Item = (PathBuf, String)>>(
+    ninja: &Ninja,
+    texts: I,
+) -> Result<Ninja, Diagnostic> {
+    let mut overrides = ninja.sm.overrides.clone();
+    overrides.extend(texts);
 
-    set_file_mtime(&source.path, mtime).unwrap();
+    Ninja::load_impl(
+        SourceManager::with_overrides(overrides),
+        ninja.root_path(),
+        ninja.options,
+    )
 }
 
-fn generate_new_file(original_text: &str, mut changes: Vec<ChangeRaw>) -> String {
+fn write_file(source: &Source, text: &str) -> io::Result<()> {
+    let mtime = FileTime::from_last_modification_time(&source.path.metadata()?);
+
+    fs::write(&source.path, text)?;
+
+    set_file_mtime(&source.path, mtime)
+}
+
+/// Fails with the location of a change overlapping the one before it.
+/// Insertions at the same place are all kept; the same replacement made
+/// twice is kept once.
+fn generate_new_file(
+    original_text: &str,
+    changes: &[ChangeRaw],
+) -> Result<GeneratedFile, Location> {
+    let mut changes: Vec<_> = changes.iter().collect();
     changes.sort_by_key(|x| x.loc);
+    changes
+        .dedup_by(|a, b| a.loc == b.loc && a.loc.start != a.loc.stop && a.new_text == b.new_text);
+    for pair in changes.windows(2) {
+        if pair[1].loc.start < pair[0].loc.stop {
+            return Err(pair[1].loc);
+        }
+    }
 
     let mut text = String::with_capacity(original_text.len());
+    let mut changed = Vec::with_capacity(changes.len());
     let mut original_text_offset = 0;
 
     for i in changes {
         text += &original_text[original_text_offset..i.loc.start];
-        text += i.new_text;
+        let start = text.len();
+        text += &i.new_text;
+        changed.push((i.loc, start..text.len()));
         original_text_offset = i.loc.stop;
     }
 
     text += &original_text[original_text_offset..];
 
-    text
+    Ok(GeneratedFile { text, changed })
+}
+
+#[cfg(all(test, feature = "regex"))]
+mod tests {
+    use super::*;
+    use fs_err as fs;
+    use regex::Regex;
+
+    const MANIFEST: &str = "\
+pipe = x|y.o
+rule cc
+  command = cc $in -o $out
+build out/a.o: cc a.c
+build out/b.o: cc b.c
+build obj/a.o: cc x.c
+build my$ dir/c.o: cc c.c
+build $pipe: cc y.c
+build all: phony out/a.o out/b.o obj/a.o my$ dir/c.o
+";
+
+    fn rewrite(ninja: &Ninja, regex: &str, replacement: &str) -> Result<String, RewriteError> {
+        let mut changes = ninja.change();
+        changes.rewrite_paths(&Regex::new(regex).unwrap(), replacement)?;
+        Ok(changes.apply().unwrap().sm.sources[0].text().to_string())
+    }
+
+    #[test]
+    fn rewrite_paths_collisions() {
+        let dir = std::env::temp_dir().join(format!("ninja_editor_rewrite_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.ninja");
+        fs::write(&path, MANIFEST).unwrap();
+        let ninja = Ninja::load(&path);
+
+        // Onto a node keeping its path.
+        let error = rewrite(&ninja, "^out/", "obj/").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`out/a.o`, `obj/a.o` would share the path `obj/a.o`"
+        );
+        // Two renamed nodes onto the same path.
+        let error = rewrite(&ninja, r"^out/[ab]\.o$", "out/ab.o").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`out/a.o`, `out/b.o` would share the path `out/ab.o`"
+        );
+        // A path that can't be written, after others that could.
+        let mut changes = ninja.change();
+        let error = changes
+            .rewrite_paths(&Regex::new(r"\.o$").unwrap(), ".obj")
+            .unwrap_err();
+        assert!(matches!(error, RewriteError::Escape(_)));
+        assert_eq!(changes.apply().unwrap().sm.sources[0].text(), MANIFEST);
+
+        // `obj/a.o` moves out of the way, so `out/a.o` can take its path.
+        let text = rewrite(&ninja, "^(out|obj)/", "${1}x/").unwrap();
+        assert!(text.contains("build outx/a.o: cc a.c\n"));
+        assert!(text.contains("build objx/a.o: cc x.c\n"));
+        let text = rewrite(&ninja, "^my dir/", "your dir:/").unwrap();
+        assert!(text.contains("build your$ dir$:/c.o: cc c.c\n"));
+    }
+
+    #[test]
+    fn rewrite_paths_after_a_rename() {
+        let dir = std::env::temp_dir().join(format!(
+            "ninja_editor_rewrite_renamed_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.ninja");
+        fs::write(&path, MANIFEST).unwrap();
+        let ninja = Ninja::load(&path);
+
+        // `obj/a.o` is renamed first, which frees its path.
+        let mut changes = ninja.change();
+        changes
+            .rename_node(ninja.data().node("obj/a.o").unwrap(), "obj/x.o")
+            .unwrap();
+        let counts = changes
+            .rewrite_paths(&Regex::new("^out/").unwrap(), "obj/")
+            .unwrap();
+        assert_eq!(counts, [(path, 4)]);
+    }
 }
@@ -0,0 +1,150 @@
+use crate::{Data, EdgeKey, PathKind};
+use fs_err as fs;
+use std::{io, path::Path, path::PathBuf};
+
+#[derive(Debug, Clone, Default)]
+pub struct CompdbOptions {
+    /// Only edges of rules with these names; when empty, edges that compile
+    /// a C, C++ or Objective-C source.
+    pub rules: Vec<String>,
+    /// Replaces `@rspfile` in commands with the content of the response file,
+    /// like `ninja -t compdb -x`.
+    pub expand_rspfile: bool,
+    /// The directory the commands run in; the working directory if empty.
+    pub directory: PathBuf,
+}
+
+/// An entry of `compile_commands.json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileCommand {
+    pub edge: EdgeKey,
+    pub directory: String,
+    pub command: String,
+    /// The first input of the edge.
+    pub file: String,
+    /// The first output of the edge.
+    pub output: String,
+}
+
+const SOURCE_EXTENSIONS: [&str; 12] = [
+    "c", "cc", "cpp", "cxx", "c++", "cp", "C", "m", "mm", "M", "cu", "ixx",
+];
+
+impl Data<'_> {
+    /// The commands of the edges selected by `options`, as `ninja -t compdb`
+    /// evaluates them.
+    pub fn compile_commands(&self, options: &CompdbOptions) -> io::Result<Vec<CompileCommand>> {
+        let directory = match options.directory.as_os_str().is_empty() {
+            true => std::env::current_dir()?,
+            false => options.directory.clone(),
+        };
+        let directory = directory.to_string_lossy().into_owned();
+
+        let mut ret = Vec::new();
+        for (key, edge) in &self.edges {
+            let mut inputs = [PathKind::In, PathKind::ImplicitIn, PathKind::OrderOnlyIn]
+                .into_iter()
+                .flat_map(|kind| edge.paths(kind));
+            let Some(file) = inputs.next() else {
+                continue;
+            };
+            let file = &self.nodes[file.elem].path;
+            let rule = self.rules[edge.rule].name.elem;
+            let selected = match options.rules.is_empty() {
+                true => rule != "phony" && is_source(file),
+                false => options.rules.iter().any(|x| x == rule),
+            };
+            if !selected {
+                continue;
+            }
+
+            let mut command = self.evaluate_edge(key, "command");
+            if options.expand_rspfile {
+                command = self.expand_rspfile(key, command);
+            }
+            let output = edge
+                .paths(PathKind::Out)
+                .first()
+                .map(|x| self.nodes[x.elem].path.clone())
+                .unwrap_or_default();
+            ret.push(CompileCommand {
+                edge: key,
+                directory: directory.clone(),
+                command,
+                file: file.clone(),
+                output,
+            });
+        }
+        Ok(ret)
+    }
+
+    /// Writes [`Data::compile_commands`] to `path` as JSON.
+    pub fn write_compdb<P: AsRef<Path>>(&self, path: P, options: &CompdbOptions) -> io::Result<()> {
+        let commands = self.compile_commands(options)?;
+        fs::write(path.as_ref(), CompileCommand::to_json(&commands))
+    }
+
+    fn expand_rspfile(&self, edge: EdgeKey, command: String) -> String {
+        let rspfile = self.evaluate_edge_unescaped(edge, "rspfile");
+        if rspfile.is_empty() {
+            return command;
+        }
+        match command.find(&rspfile) {
+            Some(n) if n > 0 && command.as_bytes()[n - 1] == b'@' => {
+                let content = self
+                    .evaluate_edge(edge, "rspfile_content")
+                    .replace('\n', " ");
+                let mut command = command;
+                command.replace_range(n - 1..n + rspfile.len(), &content);
+                command
+            }
+            _ => command,
+        }
+    }
+}
+
+impl CompileCommand {
+    /// The commands in the format of `compile_commands.json`, written the way
+    /// ninja writes it.
+    pub fn to_json(commands: &[CompileCommand]) -> String {
+        let mut out = String::from("[");
+        for (n, i) in commands.iter().enumerate() {
+            if n != 0 {
+                out.push(',');
+            }
+            out += "\n  {\n    \"directory\": \"";
+            json_string(&mut out, &i.directory);
+            out += "\",\n    \"command\": \"";
+            json_string(&mut out, &i.command);
+            out += "\",\n    \"file\": \"";
+            json_string(&mut out, &i.file);
+            out += "\",\n    \"output\": \"";
+            json_string(&mut out, &i.output);
+            out += "\"\n  }";
+        }
+        out += "\n]\n";
+        out
+    }
+}
+
+fn is_source(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|x| SOURCE_EXTENSIONS.iter().any(|&e| x == e))
+}
+
+fn json_string(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '"' => *out += "\\\"",
+            '\\' => *out += "\\\\",
+            '\n' => *out += "\\n",
+            '\r' => *out += "\\r",
+            '\t' => *out += "\\t",
+            '\u{8}' => *out += "\\b",
+            '\u{c}' => *out += "\\f",
+            c if (c as u32) < 0x20 => *out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+}
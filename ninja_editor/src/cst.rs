@@ -0,0 +1,500 @@
+use crate::{
+    diagnostic::{Diagnostic, ParseError},
+    lexer::{Lexer, Location, Token, TokenKind, LOC_INVALID},
+    SourceId,
+};
+use fs_err as fs;
+use std::{
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+type K = TokenKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    /// `build`, `rule`, `pool`, `default`, `include` or `subninja`.
+    Keyword,
+    Ident,
+    Equals,
+    Colon,
+    Pipe,
+    Pipe2,
+    PipeAt,
+    /// A path, or the part of it between two continuations.
+    Path,
+    /// A variable value, or the part of it between two continuations.
+    Value,
+    /// `#` and the rest of the line, without the line break.
+    Comment,
+    Newline,
+    /// The spaces starting a binding line.
+    Indent,
+    Whitespace,
+    /// `$` followed by a line break.
+    Continuation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxToken {
+    pub kind: SyntaxKind,
+    pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Rule,
+    Build,
+    Pool,
+    Default,
+    Include,
+    Subninja,
+    /// A top level `name = value`.
+    Variable,
+    /// An indented `name = value` belonging to a rule, build or pool.
+    Binding,
+    Comment,
+    Blank,
+}
+
+/// A statement and everything on its lines. `rule`, `build` and `pool` have
+/// their bindings, and the comments between them, as children.
+#[derive(Debug, Clone)]
+pub struct SyntaxItem {
+    pub kind: ItemKind,
+    pub tokens: Vec<SyntaxToken>,
+    pub children: Vec<SyntaxItem>,
+}
+impl SyntaxItem {
+    fn new(kind: ItemKind) -> SyntaxItem {
+        SyntaxItem {
+            kind,
+            tokens: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+    /// Calls `f` for the tokens of the item and of its children, in order.
+    pub fn for_each_token<F: FnMut(&SyntaxToken)>(&self, f: &mut F) {
+        self.tokens.iter().for_each(&mut *f);
+        for i in &self.children {
+            i.for_each_token(f);
+        }
+    }
+    pub fn range(&self) -> Range<usize> {
+        let start = self.tokens.first().map_or(0, |x| x.range.start);
+        let mut end = start;
+        self.for_each_token(&mut |x| end = x.range.end);
+        start..end
+    }
+    pub fn first(&self, kind: SyntaxKind) -> Option<&SyntaxToken> {
+        self.tokens.iter().find(|x| x.kind == kind)
+    }
+}
+
+/// A lossless syntax tree of a single manifest file: every byte of the text
+/// belongs to exactly one token, so printing the tree gives back the text.
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    text: String,
+    source_id: SourceId,
+    items: Vec<SyntaxItem>,
+}
+impl SyntaxTree {
+    /// `path` is only used for diagnostics.
+    pub fn parse<P: Into<PathBuf>>(path: P, text: &str) -> Result<SyntaxTree, Diagnostic> {
+        SyntaxTree::build(text, LOC_INVALID.source_id)
+            .map_err(|e| Diagnostic::in_text(path, text, e))
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SyntaxTree, Diagnostic> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).map_err(|e| Diagnostic::for_file(path, e.to_string()))?;
+        SyntaxTree::parse(path, &text)
+    }
+    pub(crate) fn build(text: &str, source_id: SourceId) -> Result<SyntaxTree, ParseError> {
+        let mut lexer_text = String::with_capacity(text.len() + 1);
+        lexer_text += text;
+        lexer_text.push('\0');
+
+        let mut builder = Builder {
+            lexer: Lexer::new(&lexer_text, source_id),
+            text: &lexer_text,
+        };
+        let items = builder.items()?;
+
+        Ok(SyntaxTree {
+            text: text.to_string(),
+            source_id,
+            items,
+        })
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn items(&self) -> &[SyntaxItem] {
+        &self.items
+    }
+    pub fn str(&self, token: &SyntaxToken) -> &str {
+        &self.text[token.range.clone()]
+    }
+    /// The location of a token, to be edited with a `ChangeList` of the
+    /// `Ninja` this tree was made from.
+    pub fn loc(&self, token: &SyntaxToken) -> Location {
+        Location {
+            start: token.range.start,
+            stop: token.range.end,
+            source_id: self.source_id,
+        }
+    }
+}
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut result = Ok(());
+        for i in &self.items {
+            i.for_each_token(&mut |x| {
+                if result.is_ok() {
+                    result = f.write_str(self.str(x));
+                }
+            });
+        }
+        result
+    }
+}
+
+struct Builder<'x> {
+    lexer: Lexer<'x>,
+    /// The text with the terminating nul the lexer needs.
+    text: &'x str,
+}
+impl<'x> Builder<'x> {
+    fn items(&mut self) -> Result<Vec<SyntaxItem>, ParseError> {
+        let mut items = Vec::new();
+        loop {
+            let token = self.lexer.next_impl()?;
+            let item = match token.kind {
+                K::Eof => break,
+                K::Comment => self.single(ItemKind::Comment, SyntaxKind::Comment, token),
+                K::Newline => self.single(ItemKind::Blank, SyntaxKind::Newline, token),
+                K::Ident => {
+                    let mut item = SyntaxItem::new(ItemKind::Variable);
+                    self.push(&mut item.tokens, SyntaxKind::Ident, token.loc);
+                    self.assignment(&mut item.tokens)?;
+                    item
+                }
+                K::Rule | K::Pool => {
+                    let kind = match token.kind {
+                        K::Rule => ItemKind::Rule,
+                        _ => ItemKind::Pool,
+                    };
+                    let mut item = SyntaxItem::new(kind);
+                    self.push(&mut item.tokens, SyntaxKind::Keyword, token.loc);
+                    self.expect(&mut item.tokens, K::Ident, SyntaxKind::Ident)?;
+                    self.line_end(&mut item.tokens)?;
+                    item.children = self.block()?;
+                    item
+                }
+                K::Build => self.build(token)?,
+                K::Default | K::Include | K::Subninja => {
+                    let kind = match token.kind {
+                        K::Default => ItemKind::Default,
+                        K::Include => ItemKind::Include,
+                        _ => ItemKind::Subninja,
+                    };
+                    let mut item = SyntaxItem::new(kind);
+                    self.push(&mut item.tokens, SyntaxKind::Keyword, token.loc);
+                    self.paths(&mut item.tokens)?;
+                    self.line_end(&mut item.tokens)?;
+                    item
+                }
+                _ => {
+                    return Err(ParseError::new(
+                        format!("unexpected {:?}", token.kind),
+                        token.loc,
+                    ))
+                }
+            };
+            items.push(item);
+        }
+
+        Ok(items)
+    }
+
+    fn build(&mut self, keyword: Token) -> Result<SyntaxItem, ParseError> {
+        let mut item = SyntaxItem::new(ItemKind::Build);
+        let tokens = &mut item.tokens;
+        self.push(tokens, SyntaxKind::Keyword, keyword.loc);
+
+        self.paths(tokens)?;
+        if self.eat(tokens, K::Pipe, SyntaxKind::Pipe)? {
+            self.paths(tokens)?;
+        }
+        self.expect(tokens, K::Colon, SyntaxKind::Colon)?;
+        self.expect(tokens, K::Ident, SyntaxKind::Ident)?;
+        self.paths(tokens)?;
+        if self.eat(tokens, K::Pipe, SyntaxKind::Pipe)? {
+            self.paths(tokens)?;
+        }
+        if self.eat(tokens, K::Pipe2, SyntaxKind::Pipe2)? {
+            self.paths(tokens)?;
+        }
+        if self.eat(tokens, K::PipeAt, SyntaxKind::PipeAt)? {
+            self.paths(tokens)?;
+        }
+        self.line_end(tokens)?;
+
+        item.children = self.block()?;
+        Ok(item)
+    }
+
+    /// The bindings of a `rule`, `build` or `pool`. Comments only belong to
+    /// the block if a binding follows them.
+    fn block(&mut self) -> Result<Vec<SyntaxItem>, ParseError> {
+        let mut children = Vec::new();
+        let mut comments = Vec::new();
+        let mut block_end = self.lexer.offset();
+
+        loop {
+            let token = self.lexer.next_impl()?;
+            match token.kind {
+                K::Comment => {
+                    comments.push(self.single(ItemKind::Comment, SyntaxKind::Comment, token));
+                }
+                K::Indent => {
+                    let mut binding = SyntaxItem::new(ItemKind::Binding);
+                    self.push(&mut binding.tokens, SyntaxKind::Indent, token.loc);
+                    let name = self.lexer.next_impl()?;
+                    if !matches!(name.kind, K::Ident | K::Pool) {
+                        return Err(ParseError::new("expected variable name", name.loc));
+                    }
+                    self.push(&mut binding.tokens, SyntaxKind::Ident, name.loc);
+                    self.assignment(&mut binding.tokens)?;
+
+                    children.append(&mut comments);
+                    children.push(binding);
+                    block_end = self.lexer.offset();
+                }
+                _ => break,
+            }
+        }
+
+        self.lexer.set_offset(block_end);
+        Ok(children)
+    }
+
+    /// `= value` and the line break ending it.
+    fn assignment(&mut self, tokens: &mut Vec<SyntaxToken>) -> Result<(), ParseError> {
+        self.expect(tokens, K::Equals, SyntaxKind::Equals)?;
+        let value = self.lexer.read_var_value()?;
+        self.split(tokens, SyntaxKind::Value, value.loc.start..value.loc.stop);
+        Ok(())
+    }
+
+    fn paths(&mut self, tokens: &mut Vec<SyntaxToken>) -> Result<(), ParseError> {
+        loop {
+            let path = self.lexer.read_path()?;
+            self.push(tokens, SyntaxKind::Path, path.loc);
+            if path.elem.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn line_end(&mut self, tokens: &mut Vec<SyntaxToken>) -> Result<(), ParseError> {
+        let token = self.lexer.next_impl()?;
+        match token.kind {
+            K::Newline => self.push(tokens, SyntaxKind::Newline, token.loc),
+            // Leave it for `items` to stop at.
+            K::Eof => self.lexer.set_offset(token.loc.start),
+            _ => {
+                return Err(ParseError::new(
+                    format!("expected Newline, got {:?}", token.kind),
+                    token.loc,
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn expect(
+        &mut self,
+        tokens: &mut Vec<SyntaxToken>,
+        kind: K,
+        syntax: SyntaxKind,
+    ) -> Result<(), ParseError> {
+        let token = self.lexer.next_impl()?;
+        if token.kind != kind {
+            return Err(ParseError::new(
+                format!("expected {:?}, got {:?}", kind, token.kind),
+                token.loc,
+            ));
+        }
+        self.push(tokens, syntax, token.loc);
+        Ok(())
+    }
+
+    fn eat(
+        &mut self,
+        tokens: &mut Vec<SyntaxToken>,
+        kind: K,
+        syntax: SyntaxKind,
+    ) -> Result<bool, ParseError> {
+        let offset = self.lexer.offset();
+        let token = self.lexer.next_impl()?;
+        if token.kind != kind {
+            self.lexer.set_offset(offset);
+            return Ok(false);
+        }
+        self.push(tokens, syntax, token.loc);
+        Ok(true)
+    }
+
+    fn single(&mut self, kind: ItemKind, syntax: SyntaxKind, token: Token) -> SyntaxItem {
+        let mut item = SyntaxItem::new(kind);
+        self.push(&mut item.tokens, syntax, token.loc);
+        item
+    }
+
+    /// Pushes a lexer token and the whitespace the lexer skipped after it.
+    fn push(&self, tokens: &mut Vec<SyntaxToken>, kind: SyntaxKind, loc: Location) {
+        self.split(tokens, kind, loc.start..loc.stop);
+        self.split(
+            tokens,
+            SyntaxKind::Whitespace,
+            loc.stop..self.lexer.offset(),
+        );
+    }
+
+    /// Pushes `range` as tokens of `kind`, separating the spaces, line breaks
+    /// and continuations the lexer folds into it.
+    fn split(&self, tokens: &mut Vec<SyntaxToken>, kind: SyntaxKind, range: Range<usize>) {
+        let bytes = self.text.as_bytes();
+        let mut push = |kind, range: Range<usize>| {
+            if !range.is_empty() {
+                tokens.push(SyntaxToken { kind, range });
+            }
+        };
+        let spaces_end = |mut i: usize| {
+            while i < range.end && bytes[i] == b' ' {
+                i += 1;
+            }
+            i
+        };
+        let newline_len = |i: usize| match bytes[i..range.end] {
+            [b'\n', ..] => 1,
+            [b'\r', b'\n', ..] => 2,
+            _ => 0,
+        };
+
+        let mut start = range.start;
+        // Comments and line breaks include the spaces before them.
+        if matches!(kind, SyntaxKind::Comment | SyntaxKind::Newline) {
+            start = spaces_end(start);
+            push(SyntaxKind::Whitespace, range.start..start);
+        }
+
+        let mut i = start;
+        while i < range.end {
+            if kind == SyntaxKind::Whitespace && bytes[i] == b' ' {
+                i = spaces_end(i);
+                continue;
+            }
+            if bytes[i] == b'$' && kind != SyntaxKind::Comment {
+                let len = newline_len(i + 1);
+                if len == 0 {
+                    i += 2;
+                    continue;
+                }
+                push(kind, start..i);
+                push(SyntaxKind::Continuation, i..i + 1 + len);
+                start = spaces_end(i + 1 + len);
+                push(SyntaxKind::Whitespace, i + 1 + len..start);
+                i = start;
+                continue;
+            }
+            if newline_len(i) != 0 {
+                push(kind, start..i);
+                push(SyntaxKind::Newline, i..range.end);
+                return;
+            }
+            i += 1;
+        }
+        push(kind, start..range.end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCES: &[&str] = &[
+        "",
+        "\n\n",
+        "# only a comment\n",
+        "cflags = -O2   # not a comment, part of the value\n",
+        "rule cc\n  command = cc $in -o $out\n  # inside the block\n  description = CC $out\n# after it\n",
+        "build a.o b.o | a.d: cc a.c | a.h || gen |@ lint\n  cflags = -g\n",
+        "build out$ dir/a$:b.o: cc $\n    a.c $\n    b.c\n",
+        "x = ${cflags}$$ $ y$:z\n",
+        "pool link\n  depth = 4\n\ndefault a.o b.o\ninclude rules.ninja\nsubninja sub/build.ninja\n",
+        "rule cc\r\n  command = cc $\r\n      $in\r\n\r\n# crlf\r\nbuild a.o: cc a.c\r\n",
+        "  \n   # indented comment\nbuild a: phony   \nbuild b: phony a\n",
+    ];
+
+    fn tokens(tree: &SyntaxTree) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        for item in tree.items() {
+            item.for_each_token(&mut |x| tokens.push(x.clone()));
+        }
+        tokens
+    }
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        for &text in SOURCES {
+            let tree = SyntaxTree::parse("build.ninja", text).expect(text);
+            let tokens = tokens(&tree);
+            let mut offset = 0;
+            for token in &tokens {
+                assert_eq!(
+                    token.range.start, offset,
+                    "gap before {token:?} in {text:?}"
+                );
+                assert!(!token.range.is_empty(), "empty {token:?} in {text:?}");
+                offset = token.range.end;
+            }
+            assert_eq!(offset, text.len(), "{text:?}");
+            let concatenated: String = tokens.iter().map(|x| tree.str(x)).collect();
+            assert_eq!(concatenated, text);
+            assert_eq!(tree.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn comments_in_blocks() {
+        let tree = SyntaxTree::parse("build.ninja", SOURCES[4]).unwrap();
+        let kinds: Vec<_> = tree.items().iter().map(|x| x.kind).collect();
+        assert_eq!(kinds, [ItemKind::Rule, ItemKind::Comment]);
+        let children: Vec<_> = tree.items()[0].children.iter().map(|x| x.kind).collect();
+        assert_eq!(
+            children,
+            [ItemKind::Binding, ItemKind::Comment, ItemKind::Binding]
+        );
+    }
+
+    #[test]
+    fn continuations_and_crlf() {
+        let tree = SyntaxTree::parse("build.ninja", SOURCES[9]).unwrap();
+        let texts = |kind| -> Vec<_> {
+            tokens(&tree)
+                .iter()
+                .filter(|x| x.kind == kind)
+                .map(|x| tree.str(x).to_string())
+                .collect()
+        };
+        assert_eq!(texts(SyntaxKind::Continuation), ["$\r\n"]);
+        assert_eq!(texts(SyntaxKind::Value), ["cc ", "$in"]);
+        assert!(texts(SyntaxKind::Newline).iter().all(|x| x == "\r\n"));
+        assert_eq!(texts(SyntaxKind::Comment), ["# crlf"]);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    lexer::LOC_INVALID, Data, Diagnostic, EdgeKey, Location, Ninja, NodeKey, PathKind,
+    ValidationError,
+};
+use std::collections::HashMap;
+
+/// A dependency cycle, dependents first: the edge of each step has the node
+/// of the next step as an input, and the edge of the last step has the node
+/// of the first one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle {
+    pub steps: Vec<CycleStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleStep {
+    pub node: NodeKey,
+    /// The edge building `node`.
+    pub edge: EdgeKey,
+    /// Where the node of the next step is written as an input of `edge`;
+    /// `None` if a dyndep file or a `Data` building function added it.
+    pub input_loc: Option<Location>,
+}
+
+impl Cycle {
+    /// The paths of the cycle the way ninja prints them, `a -> b -> a`.
+    pub fn chain(&self, data: &Data) -> String {
+        let first = self.steps.first().map(|x| x.node);
+        let paths: Vec<_> = self
+            .steps
+            .iter()
+            .map(|x| x.node)
+            .chain(first)
+            .map(|x| data.nodes[x].path.as_str())
+            .collect();
+        paths.join(" -> ")
+    }
+}
+
+impl Data<'_> {
+    /// The dependency cycles of the graph, including the inputs added by
+    /// loaded dyndep files.
+    ///
+    /// There is one cycle for every dependency a depth-first search finds
+    /// leading back to a node it is still exploring, so every node that is
+    /// part of a cycle is in at least one, without listing the exponentially
+    /// many cycles a dense graph can have.
+    ///
+    /// Like ninja, a `phony` edge with a single output naming itself as an
+    /// input, explicit, implicit or order-only, isn't a cycle; old CMake
+    /// versions write those.
+    pub fn cycles(&self) -> Vec<Cycle> {
+        let mut cycles = Vec::new();
+        // Nodes on the current path are `false`, finished ones `true`.
+        let mut visited: HashMap<NodeKey, bool> = HashMap::new();
+        for start in self.nodes.keys() {
+            if visited.contains_key(&start) {
+                continue;
+            }
+            visited.insert(start, false);
+            let mut stack = vec![(start, self.cycle_inputs(start), 0)];
+            while let Some((node, inputs, n)) = stack.last_mut() {
+                let Some(&input) = inputs.get(*n) else {
+                    visited.insert(*node, true);
+                    stack.pop();
+                    continue;
+                };
+                *n += 1;
+                match visited.get(&input) {
+                    Some(true) => {}
+                    Some(false) => {
+                        let from = stack.iter().position(|x| x.0 == input).unwrap();
+                        let nodes: Vec<_> = stack[from..].iter().map(|x| x.0).collect();
+                        cycles.push(self.cycle(&nodes));
+                    }
+                    None => {
+                        visited.insert(input, false);
+                        stack.push((input, self.cycle_inputs(input), 0));
+                    }
+                }
+            }
+        }
+        cycles
+    }
+
+    /// The inputs of the edge building `node`.
+    fn cycle_inputs(&self, node: NodeKey) -> Vec<NodeKey> {
+        let Some(key) = self.nodes[node].in_edge else {
+            return Vec::new();
+        };
+        let edge = &self.edges[key];
+        let phony_self_reference = self.rules[edge.rule].file.is_none()
+            && edge.outs.len() == 1
+            && edge.implicit_outs.is_empty();
+        edge.inputs()
+            .map(|(_, x)| x)
+            .filter(|&x| !(phony_self_reference && x == node))
+            .collect()
+    }
+
+    /// The cycle going through `nodes`, each depending on the next one and
+    /// the last on the first.
+    fn cycle(&self, nodes: &[NodeKey]) -> Cycle {
+        let next = nodes.iter().skip(1).chain(nodes.first());
+        let steps = nodes
+            .iter()
+            .zip(next)
+            .map(|(&node, &input)| {
+                let edge = self.nodes[node].in_edge.unwrap();
+                let input_loc = self.edges[edge]
+                    .all_paths()
+                    .filter(|(kind, _)| !kind.is_output() && *kind != PathKind::Validation)
+                    .find(|(_, x)| x.elem == input)
+                    .map(|(_, x)| x.loc())
+                    .filter(|&x| x != LOC_INVALID);
+                CycleStep {
+                    node,
+                    edge,
+                    input_loc,
+                }
+            })
+            .collect();
+        Cycle { steps }
+    }
+}
+
+impl Ninja {
+    /// Fails with a diagnostic for each of [`Data::cycles`], at the `build`
+    /// statement of its first node.
+    pub fn check_cycles(&self) -> Result<(), ValidationError> {
+        let diagnostics: Vec<_> = self
+            .data
+            .cycles()
+            .iter()
+            .map(|cycle| {
+                let edge = &self.data.edges[cycle.steps[0].edge];
+                let message = format!("dependency cycle: {}", cycle.chain(&self.data));
+                Diagnostic::at(&self.sm, edge.loc, message)
+            })
+            .collect();
+        match diagnostics.is_empty() {
+            true => Ok(()),
+            false => Err(ValidationError { diagnostics }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Ninja;
+    use fs_err as fs;
+
+    #[test]
+    fn phony_self_references() {
+        let dir = std::env::temp_dir().join(format!("ninja_editor_cycle_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.ninja");
+        fs::write(
+            &path,
+            "rule cc\n  command = cc $in\n\
+             build a: phony a\n\
+             build b: phony | b\n\
+             build c: phony x || c\n\
+             build d e: phony d\n\
+             build f: cc f\n",
+        )
+        .unwrap();
+        let ninja = Ninja::load(&path);
+        let data = ninja.data();
+
+        // Only the phony edges with a single output are exempt.
+        let nodes: Vec<Vec<_>> = data
+            .cycles()
+            .iter()
+            .map(|cycle| {
+                cycle
+                    .steps
+                    .iter()
+                    .map(|x| data.nodes[x.node].path.as_str())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(nodes, [["d"], ["f"]]);
+    }
+}
@@ -0,0 +1,478 @@
+use crate::{Data, NodeKey};
+use fs_err as fs;
+use std::{collections::HashMap, fmt, io, path::Path};
+
+/// The `.ninja_deps` file where ninja keeps the dependencies it discovered
+/// with `deps = gcc` or `deps = msvc`.
+///
+/// Only version 4 is supported. The file is a header followed by records,
+/// each prefixed by its size as a little-endian `u32`:
+///
+/// - path records: the path padded with NULs to a multiple of 4 bytes, then
+///   the one's complement of the id of the path, which is its index among the
+///   path records;
+/// - deps records, which have the high bit of the size set: the id of the
+///   output, its mtime as a `u64`, then the ids of its dependencies.
+///
+/// Paths are resolved when loading, so renaming them doesn't care about ids;
+/// [`DepsLog::to_bytes`] writes a compacted log with the paths in use only.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepsLog {
+    /// Ordered by output id; an output has one entry, the last one recorded.
+    pub entries: Vec<DepsEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepsEntry {
+    pub output: String,
+    /// The mtime of the output when the dependencies were recorded, in
+    /// nanoseconds.
+    pub mtime: i64,
+    pub inputs: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum DepsLogError {
+    Io(io::Error),
+    Malformed(String),
+    /// The deps of the output don't fit in a record.
+    RecordTooLarge(String),
+}
+impl fmt::Display for DepsLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DepsLogError::Io(e) => write!(f, "{e}"),
+            DepsLogError::Malformed(e) => write!(f, "malformed deps log: {e}"),
+            DepsLogError::RecordTooLarge(output) => {
+                write!(f, "too many deps for `{output}` to fit in a record")
+            }
+        }
+    }
+}
+impl std::error::Error for DepsLogError {}
+impl From<io::Error> for DepsLogError {
+    fn from(e: io::Error) -> Self {
+        DepsLogError::Io(e)
+    }
+}
+
+const SIGNATURE: &[u8] = b"# ninjadeps\n";
+const VERSION: u32 = 4;
+const MAX_RECORD_SIZE: usize = (1 << 19) - 1;
+const DEPS_FLAG: u32 = 1 << 31;
+
+impl DepsLog {
+    pub fn parse(bytes: &[u8]) -> Result<DepsLog, DepsLogError> {
+        let malformed = |offset: usize, message: &str| {
+            DepsLogError::Malformed(format!("offset {}: {}", offset, message))
+        };
+        let Some(rest) = bytes.strip_prefix(SIGNATURE) else {
+            return Err(malformed(0, "expected `# ninjadeps`"));
+        };
+        let version = rest
+            .get(..4)
+            .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+            .ok_or_else(|| malformed(SIGNATURE.len(), "missing version"))?;
+        if version != VERSION {
+            return Err(malformed(
+                SIGNATURE.len(),
+                &format!("unsupported version {}", version),
+            ));
+        }
+
+        let mut paths: Vec<&[u8]> = Vec::new();
+        let mut ids: HashMap<&[u8], usize> = HashMap::new();
+        // Indexed by output id.
+        let mut deps: Vec<Option<(i64, Vec<usize>)>> = Vec::new();
+        let mut offset = SIGNATURE.len() + 4;
+        while offset < bytes.len() {
+            let header = bytes
+                .get(offset..offset + 4)
+                .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+                .ok_or_else(|| malformed(offset, "truncated record"))?;
+            let is_deps = header & DEPS_FLAG != 0;
+            let size = (header & !DEPS_FLAG) as usize;
+            if size > MAX_RECORD_SIZE || !size.is_multiple_of(4) {
+                return Err(malformed(offset, "invalid record size"));
+            }
+            let record = bytes
+                .get(offset + 4..offset + 4 + size)
+                .ok_or_else(|| malformed(offset, "truncated record"))?;
+            let words: Vec<u32> = record
+                .chunks_exact(4)
+                .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+                .collect();
+
+            if is_deps {
+                let [output, mtime_low, mtime_high, ref inputs @ ..] = words[..] else {
+                    return Err(malformed(offset, "deps record too short"));
+                };
+                let id = |x: u32| match (x as usize) < paths.len() {
+                    true => Ok(x as usize),
+                    false => Err(malformed(offset, "unknown path id")),
+                };
+                let output = id(output)?;
+                let mtime = (u64::from(mtime_high) << 32 | u64::from(mtime_low)) as i64;
+                let inputs = inputs.iter().map(|&x| id(x)).collect::<Result<_, _>>()?;
+                deps[output] = Some((mtime, inputs));
+            } else {
+                let Some((&checksum, _)) = words.split_last() else {
+                    return Err(malformed(offset, "path record too short"));
+                };
+                let path = trim_padding(&record[..size - 4]);
+                if !checksum as usize != paths.len() {
+                    return Err(malformed(offset, "path record checksum mismatch"));
+                }
+                if ids.insert(path, paths.len()).is_some() {
+                    return Err(malformed(offset, "duplicate path"));
+                }
+                paths.push(path);
+                deps.push(None);
+            }
+            offset += 4 + size;
+        }
+
+        let string = |id: usize| {
+            std::str::from_utf8(paths[id])
+                .map(str::to_string)
+                .map_err(|_| DepsLogError::Malformed(format!("path {} isn't UTF-8", id)))
+        };
+        let mut log = DepsLog::default();
+        for (output, deps) in deps.into_iter().enumerate() {
+            if let Some((mtime, inputs)) = deps {
+                log.entries.push(DepsEntry {
+                    output: string(output)?,
+                    mtime,
+                    inputs: inputs.into_iter().map(string).collect::<Result<_, _>>()?,
+                });
+            }
+        }
+        Ok(log)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<DepsLog, DepsLogError> {
+        DepsLog::parse(&fs::read(path.as_ref())?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), DepsLogError> {
+        Ok(fs::write(path.as_ref(), self.to_bytes()?)?)
+    }
+
+    /// The log in the binary format, with path records written before the
+    /// first deps record using them, as ninja does.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DepsLogError> {
+        let mut out = SIGNATURE.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        let mut ids: HashMap<&str, u32> = HashMap::new();
+        for entry in &self.entries {
+            let size = 4 * (3 + entry.inputs.len());
+            if size > MAX_RECORD_SIZE {
+                return Err(DepsLogError::RecordTooLarge(entry.output.clone()));
+            }
+            let mut path_ids = Vec::with_capacity(1 + entry.inputs.len());
+            for path in std::iter::once(&entry.output).chain(&entry.inputs) {
+                let id = match ids.get(path.as_str()) {
+                    Some(&id) => id,
+                    None => {
+                        let id = ids.len() as u32;
+                        write_path(&mut out, path)?;
+                        out.extend_from_slice(&(!id).to_le_bytes());
+                        ids.insert(path, id);
+                        id
+                    }
+                };
+                path_ids.push(id);
+            }
+            out.extend_from_slice(&(size as u32 | DEPS_FLAG).to_le_bytes());
+            out.extend_from_slice(&path_ids[0].to_le_bytes());
+            out.extend_from_slice(&(entry.mtime as u64).to_le_bytes());
+            for id in &path_ids[1..] {
+                out.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn entry(&self, output: &str) -> Option<&DepsEntry> {
+        self.entries.iter().find(|x| x.output == output)
+    }
+
+    /// The outputs that depend on `input`.
+    pub fn dependents<'a>(&'a self, input: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |x| x.inputs.iter().any(|x| x == input))
+            .map(|x| x.output.as_str())
+    }
+
+    /// The entries of the outputs of `data`, by node.
+    pub fn by_node(&self, data: &Data) -> HashMap<NodeKey, &DepsEntry> {
+        self.entries
+            .iter()
+            .filter_map(|x| Some((data.node(&x.output)?, x)))
+            .collect()
+    }
+
+    /// Renames the paths for which `rename` returns a new path, outputs and
+    /// inputs alike; returns how many entries changed. When an output is
+    /// renamed to one that already has an entry, the renamed one is kept.
+    pub fn rename_with<F: FnMut(&str) -> Option<String>>(&mut self, mut rename: F) -> usize {
+        let mut count = 0;
+        let mut renamed = Vec::with_capacity(self.entries.len());
+        for entry in &mut self.entries {
+            let output = rename(&entry.output);
+            let mut changed = output.is_some();
+            if let Some(path) = output {
+                entry.output = path;
+            }
+            for input in &mut entry.inputs {
+                if let Some(path) = rename(input) {
+                    *input = path;
+                    changed = true;
+                }
+            }
+            renamed.push(changed);
+            count += usize::from(changed);
+        }
+
+        // Keep one entry per output, preferring renamed ones.
+        let mut kept: HashMap<&str, usize> = HashMap::new();
+        for (n, entry) in self.entries.iter().enumerate() {
+            match kept.get(entry.output.as_str()) {
+                Some(&old) if !renamed[n] && renamed[old] => {}
+                _ => {
+                    kept.insert(&entry.output, n);
+                }
+            }
+        }
+        let kept: Vec<bool> = (0..self.entries.len())
+            .map(|n| kept[self.entries[n].output.as_str()] == n)
+            .collect();
+        let mut kept = kept.into_iter();
+        self.entries.retain(|_| kept.next().unwrap());
+        count
+    }
+
+    /// Renames `from` to `to`; returns how many entries changed.
+    pub fn rename(&mut self, from: &str, to: &str) -> usize {
+        self.rename_with(|x| (x == from).then(|| to.to_string()))
+    }
+
+    /// Replaces `from` with `to` at the start of every path starting with
+    /// `from`; returns how many entries changed.
+    pub fn rename_prefix(&mut self, from: &str, to: &str) -> usize {
+        self.rename_with(|x| Some(format!("{}{}", to, x.strip_prefix(from)?)))
+    }
+}
+
+fn trim_padding(path: &[u8]) -> &[u8] {
+    let mut path = path;
+    for _ in 0..3 {
+        match path.strip_suffix(b"\0") {
+            Some(x) => path = x,
+            None => break,
+        }
+    }
+    path
+}
+
+fn write_path(out: &mut Vec<u8>, path: &str) -> Result<(), DepsLogError> {
+    let padding = (4 - path.len() % 4) % 4;
+    let size = path.len() + padding + 4;
+    if size > MAX_RECORD_SIZE {
+        return Err(DepsLogError::RecordTooLarge(path.to_string()));
+    }
+    out.extend_from_slice(&(size as u32).to_le_bytes());
+    out.extend_from_slice(path.as_bytes());
+    out.extend(std::iter::repeat_n(0, padding));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(output: &str, mtime: i64, inputs: &[&str]) -> DepsEntry {
+        DepsEntry {
+            output: output.to_string(),
+            mtime,
+            inputs: inputs.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+
+    /// A log with raw records, each given as its header and its bytes.
+    fn raw(records: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut bytes = SIGNATURE.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        for (header, record) in records {
+            bytes.extend_from_slice(&header.to_le_bytes());
+            bytes.extend_from_slice(record);
+        }
+        bytes
+    }
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    fn path(path: &str, id: u32) -> Vec<u8> {
+        let mut record = path.as_bytes().to_vec();
+        record.resize(path.len().next_multiple_of(4), 0);
+        record.extend_from_slice(&(!id).to_le_bytes());
+        record
+    }
+
+    fn message(bytes: &[u8]) -> String {
+        match DepsLog::parse(bytes) {
+            Err(DepsLogError::Malformed(e)) => e,
+            x => panic!("{x:?}"),
+        }
+    }
+
+    #[test]
+    fn path_padding() {
+        // Lengths 4 to 7 cover no padding and one to three NULs.
+        let log = DepsLog {
+            entries: vec![
+                entry("abcd", 1, &["abcde", "abcdef"]),
+                entry("abcdefg", -1, &["abcd"]),
+            ],
+        };
+        let bytes = log.to_bytes().unwrap();
+        assert_eq!(
+            bytes,
+            raw(&[
+                (8, &path("abcd", 0)),
+                (12, &path("abcde", 1)),
+                (12, &path("abcdef", 2)),
+                (20 | DEPS_FLAG, &words(&[0, 1, 0, 1, 2])),
+                (12, &path("abcdefg", 3)),
+                (16 | DEPS_FLAG, &words(&[3, !0, !0, 0])),
+            ])
+        );
+        assert_eq!(&bytes[20..24], b"abcd");
+        assert_eq!(&bytes[32..40], b"abcde\0\0\0");
+        assert_eq!(DepsLog::parse(&bytes).unwrap(), log);
+    }
+
+    #[test]
+    fn path_records() {
+        let a = path("a.c", 0);
+        // The checksum must be the one's complement of the id.
+        let wrong = path("a.c", 1);
+        assert_eq!(
+            message(&raw(&[(8, &wrong)])),
+            "offset 16: path record checksum mismatch"
+        );
+        let again = path("a.c", 1);
+        assert_eq!(
+            message(&raw(&[(8, &a), (8, &again)])),
+            "offset 28: duplicate path"
+        );
+        assert_eq!(
+            message(&raw(&[(0, &[])])),
+            "offset 16: path record too short"
+        );
+        assert_eq!(message(&raw(&[(6, &a)])), "offset 16: invalid record size");
+        // A deps record referring to a path recorded after it.
+        let deps = words(&[1, 0, 0]);
+        assert_eq!(
+            message(&raw(&[(8, &a), (12 | DEPS_FLAG, &deps)])),
+            "offset 28: unknown path id"
+        );
+    }
+
+    #[test]
+    fn later_record_overrides() {
+        // Ninja appends a new deps record when an output is rebuilt.
+        let bytes = raw(&[
+            (8, &path("a.o", 0)),
+            (8, &path("a.c", 1)),
+            (8, &path("a.h", 2)),
+            (16 | DEPS_FLAG, &words(&[0, 5, 0, 1])),
+            (8, &path("b.o", 3)),
+            (12 | DEPS_FLAG, &words(&[3, 6, 0])),
+            (20 | DEPS_FLAG, &words(&[0, 7, 0, 1, 2])),
+        ]);
+        let log = DepsLog::parse(&bytes).unwrap();
+        assert_eq!(
+            log.entries,
+            [entry("a.o", 7, &["a.c", "a.h"]), entry("b.o", 6, &[])]
+        );
+        // Writing compacts the log to one record per output.
+        let compacted = log.to_bytes().unwrap();
+        assert!(compacted.len() < bytes.len());
+        assert_eq!(DepsLog::parse(&compacted).unwrap(), log);
+    }
+
+    #[test]
+    fn truncated_records() {
+        let log = DepsLog {
+            entries: vec![entry("a.o", 1, &["a.c"]), entry("b.o", 2, &["a.c"])],
+        };
+        let bytes = log.to_bytes().unwrap();
+        // Ninja only stops between records, so a log cut inside one is
+        // malformed, while one cut between them has the complete entries.
+        let a_end = SIGNATURE.len() + 4 + 2 * (4 + 8) + 4 + 16;
+        assert_eq!(
+            DepsLog::parse(&bytes[..a_end]).unwrap().entries,
+            log.entries[..1]
+        );
+        assert_eq!(
+            message(&bytes[..a_end + 2]),
+            format!("offset {a_end}: truncated record")
+        );
+        assert_eq!(
+            message(&bytes[..a_end + 6]),
+            format!("offset {a_end}: truncated record")
+        );
+        assert_eq!(
+            message(&bytes[..SIGNATURE.len() + 2]),
+            "offset 12: missing version"
+        );
+        assert_eq!(
+            message(b"# ninja log v5\n"),
+            "offset 0: expected `# ninjadeps`"
+        );
+    }
+
+    #[test]
+    fn record_too_large() {
+        let inputs: Vec<_> = (0..MAX_RECORD_SIZE / 4).map(|x| format!("{x}.h")).collect();
+        let inputs: Vec<_> = inputs.iter().map(String::as_str).collect();
+        let log = DepsLog {
+            entries: vec![entry("a.o", 0, &inputs)],
+        };
+        assert!(matches!(
+            log.to_bytes(),
+            Err(DepsLogError::RecordTooLarge(output)) if output == "a.o"
+        ));
+        let long = "x".repeat(MAX_RECORD_SIZE);
+        let log = DepsLog {
+            entries: vec![entry(&long, 0, &[])],
+        };
+        assert!(matches!(
+            log.to_bytes(),
+            Err(DepsLogError::RecordTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn rename_onto_an_existing_output() {
+        let mut log = DepsLog {
+            entries: vec![
+                entry("new/a.o", 1, &["stale.h"]),
+                entry("old/a.o", 2, &["old/a.h"]),
+                entry("b.o", 3, &["c.h"]),
+            ],
+        };
+        assert_eq!(log.rename_prefix("old/", "new/"), 1);
+        // The renamed entry is the one describing the output now built there.
+        assert_eq!(
+            log.entries,
+            [entry("new/a.o", 2, &["new/a.h"]), entry("b.o", 3, &["c.h"])]
+        );
+        assert_eq!(log.rename("c.h", "d.h"), 1);
+        assert_eq!(log.dependents("d.h").collect::<Vec<_>>(), ["b.o"]);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{lexer::Location, SourceManager};
+use std::{fmt, path::PathBuf};
+
+/// An error produced while lexing or parsing, pointing at the offending text.
+#[derive(Debug, Clone)]
+pub(crate) struct ParseError {
+    pub(crate) message: String,
+    pub(crate) loc: Location,
+}
+impl ParseError {
+    pub(crate) fn new<S: Into<String>>(message: S, loc: Location) -> ParseError {
+        ParseError {
+            message: message.into(),
+            loc,
+        }
+    }
+}
+
+/// A message attached to a position in a manifest file.
+///
+/// `line` and `column` are 1-based; they are 0 when the message refers to the
+/// file as a whole (e.g. it could not be read).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+impl Diagnostic {
+    pub(crate) fn for_file<P: Into<PathBuf>, S: Into<String>>(path: P, message: S) -> Diagnostic {
+        Diagnostic {
+            path: path.into(),
+            line: 0,
+            column: 0,
+            message: message.into(),
+        }
+    }
+    pub(crate) fn at<S: Into<String>>(sm: &SourceManager, loc: Location, message: S) -> Diagnostic {
+        let source = sm.get(loc.source_id);
+        let (line, column) = source.line_col(loc.start);
+        Diagnostic {
+            path: source.path.clone(),
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+    pub(crate) fn in_text<P: Into<PathBuf>>(path: P, text: &str, error: ParseError) -> Diagnostic {
+        let (line, column) = line_col(text, error.loc.start);
+        Diagnostic {
+            path: path.into(),
+            line,
+            column,
+            message: error.message,
+        }
+    }
+    pub(crate) fn from_parse_error(sm: &SourceManager, error: ParseError) -> Diagnostic {
+        Diagnostic::at(sm, error.loc, error.message)
+    }
+}
+
+/// 1-based line and column of a byte offset.
+pub(crate) fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text.as_bytes()[..offset.min(text.len())];
+    let line = before.iter().filter(|&&x| x == b'\n').count() + 1;
+    let line_start = before
+        .iter()
+        .rposition(|&x| x == b'\n')
+        .map_or(0, |x| x + 1);
+    (line, offset - line_start + 1)
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.path.display(), self.message)
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.path.display(),
+                self.line,
+                self.column,
+                self.message
+            )
+        }
+    }
+}
+impl std::error::Error for Diagnostic {}
@@ -0,0 +1,411 @@
+use crate::{parser::RULE_VARS, Data, EdgeKey, EscapeError, FileKind, Ninja, PathKind, ScopeKey};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+/// The semantic differences between two manifests, from [`Ninja::diff`].
+///
+/// Rules and top level variables are matched by name within the scope of
+/// the same `subninja` file, edges by the node generating their first
+/// output. Its `Display` is a readable summary.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestDiff {
+    pub rules: Vec<RuleDiff>,
+    pub edges: Vec<EdgeDiff>,
+    pub variables: Vec<VariableDiff>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// A variable, binding or other named value: `old` is `None` if it was
+/// added, `new` if it was removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueDiff {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleDiff {
+    /// The `subninja` file whose scope has the rule; `None` for the root
+    /// scope.
+    pub scope: Option<String>,
+    pub name: String,
+    pub kind: DiffKind,
+    /// The unevaluated bindings that differ.
+    pub bindings: Vec<ValueDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdgeDiff {
+    /// The explicit and implicit outputs, in the new manifest unless the edge
+    /// was removed.
+    pub outputs: Vec<String>,
+    pub kind: DiffKind,
+    /// The name of the rule, if it differs.
+    pub rule: Option<ValueDiff>,
+    /// The paths of the `build` statement that were added or removed.
+    pub paths: Vec<PathDiff>,
+    /// The reserved variables, like `command`, that evaluate differently.
+    pub variables: Vec<ValueDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathDiff {
+    pub kind: PathKind,
+    pub path: String,
+    /// Whether the path was added rather than removed.
+    pub added: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableDiff {
+    /// See [`RuleDiff::scope`].
+    pub scope: Option<String>,
+    pub value: ValueDiff,
+}
+
+impl ManifestDiff {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.edges.is_empty() && self.variables.is_empty()
+    }
+}
+
+impl Ninja {
+    /// What changed from `self` to `new`, looking at the graph rather than
+    /// the text: rules, edges with their paths and evaluated commands, and
+    /// top level variables. Where things are written and their order don't
+    /// matter. Fails if a rule binding has a character a manifest can't hold.
+    pub fn diff(&self, new: &Ninja) -> Result<ManifestDiff, EscapeError> {
+        let (old, new) = (&self.data, &new.data);
+        Ok(ManifestDiff {
+            rules: diff_rules(old, new)?,
+            edges: diff_edges(old, new),
+            variables: diff_variables(old, new),
+        })
+    }
+}
+
+/// The `subninja` file of `scope`; `None` for the root scope.
+pub(crate) fn scope_name(data: &Data, scope: ScopeKey) -> Option<String> {
+    data.files
+        .values()
+        .find(|x| x.scope == scope && x.kind == FileKind::Subninja)
+        .map(|x| x.path.elem.clone())
+}
+
+/// The values of `old` and `new` that differ, in the order of `new` then of
+/// the removed ones.
+pub(crate) fn diff_values<'a, I, J>(old: I, new: J) -> Vec<ValueDiff>
+where
+    I: IntoIterator<Item = (&'a str, String)>,
+    J: IntoIterator<Item = (&'a str, String)>,
+{
+    let old: Vec<_> = old.into_iter().collect();
+    let new: Vec<_> = new.into_iter().collect();
+    let find = |values: &[(&str, String)], name: &str| {
+        values.iter().find(|x| x.0 == name).map(|x| x.1.clone())
+    };
+    let mut ret = Vec::new();
+    for (name, value) in &new {
+        let old = find(&old, name);
+        if old.as_ref() != Some(value) {
+            ret.push(ValueDiff {
+                name: name.to_string(),
+                old,
+                new: Some(value.clone()),
+            });
+        }
+    }
+    for (name, value) in &old {
+        if find(&new, name).is_none() {
+            ret.push(ValueDiff {
+                name: name.to_string(),
+                old: Some(value.clone()),
+                new: None,
+            });
+        }
+    }
+    ret
+}
+
+fn diff_rules(old: &Data, new: &Data) -> Result<Vec<RuleDiff>, EscapeError> {
+    type Rules<'a> = BTreeMap<(Option<String>, String), Vec<(&'a str, String)>>;
+    fn rules<'a>(data: &'a Data) -> Result<Rules<'a>, EscapeError> {
+        data.rules
+            .values()
+            .filter(|x| x.file.is_some())
+            .map(|rule| {
+                let scope = data.files[rule.file.unwrap()].scope;
+                let bindings = rule
+                    .bindings
+                    .iter()
+                    .map(|x| Ok((x.name.elem.as_str(), x.value.elem.to_value()?)))
+                    .collect::<Result<_, EscapeError>>()?;
+                Ok((
+                    (scope_name(data, scope), rule.name.elem.to_string()),
+                    bindings,
+                ))
+            })
+            .collect()
+    }
+    let (old, mut new) = (rules(old)?, rules(new)?);
+    let mut ret = Vec::new();
+    for ((scope, name), old) in old {
+        let (kind, bindings) = match new.remove(&(scope.clone(), name.clone())) {
+            Some(new) => (DiffKind::Changed, diff_values(old, new)),
+            None => (DiffKind::Removed, Vec::new()),
+        };
+        if kind == DiffKind::Removed || !bindings.is_empty() {
+            ret.push(RuleDiff {
+                scope,
+                name,
+                kind,
+                bindings,
+            });
+        }
+    }
+    for ((scope, name), _) in new {
+        ret.push(RuleDiff {
+            scope,
+            name,
+            kind: DiffKind::Added,
+            bindings: Vec::new(),
+        });
+    }
+    ret.sort_by(|a, b| (&a.scope, &a.name).cmp(&(&b.scope, &b.name)));
+    Ok(ret)
+}
+
+/// The edges of two manifests matched by the node generating their first
+/// output, with the edges only in `old` and only in `new`.
+pub(crate) struct EdgeMatches {
+    pub(crate) matched: Vec<(EdgeKey, EdgeKey)>,
+    pub(crate) removed: Vec<EdgeKey>,
+    pub(crate) added: Vec<EdgeKey>,
+}
+
+pub(crate) fn match_edges(old: &Data, new: &Data) -> EdgeMatches {
+    let mut matched = Vec::new();
+    let mut removed = Vec::new();
+    let mut seen = HashSet::new();
+    for old_key in old.edges.keys() {
+        let new_key = edge_outputs(old, old_key)
+            .first()
+            .and_then(|x| new.node(x))
+            .and_then(|x| new.nodes[x].in_edge)
+            .filter(|&x| seen.insert(x));
+        match new_key {
+            Some(new_key) => matched.push((old_key, new_key)),
+            None => removed.push(old_key),
+        }
+    }
+    let added = new.edges.keys().filter(|x| !seen.contains(x)).collect();
+    EdgeMatches {
+        matched,
+        removed,
+        added,
+    }
+}
+
+/// The explicit and implicit outputs of `edge`.
+pub(crate) fn edge_outputs(data: &Data, edge: EdgeKey) -> Vec<String> {
+    let edge = &data.edges[edge];
+    let outputs = edge.outs.iter().chain(&edge.implicit_outs);
+    outputs.map(|x| data.nodes[x.elem].path.clone()).collect()
+}
+
+/// The rule of two matched edges, if it differs.
+pub(crate) fn diff_rule(
+    old: &Data,
+    old_key: EdgeKey,
+    new: &Data,
+    new_key: EdgeKey,
+) -> Option<ValueDiff> {
+    let old_rule = old.rules[old.edges[old_key].rule].name.elem;
+    let new_rule = new.rules[new.edges[new_key].rule].name.elem;
+    (old_rule != new_rule).then(|| ValueDiff {
+        name: "rule".to_string(),
+        old: Some(old_rule.to_string()),
+        new: Some(new_rule.to_string()),
+    })
+}
+
+/// The paths added to and removed from two matched edges.
+pub(crate) fn diff_paths(
+    old: &Data,
+    old_key: EdgeKey,
+    new: &Data,
+    new_key: EdgeKey,
+) -> Vec<PathDiff> {
+    let paths = |data: &Data, edge: EdgeKey| -> Vec<(PathKind, String)> {
+        let paths = data.edges[edge].all_paths();
+        paths
+            .map(|(kind, x)| (kind, data.nodes[x.elem].path.clone()))
+            .collect()
+    };
+    let (old_paths, new_paths) = (paths(old, old_key), paths(new, new_key));
+    let added = new_paths.iter().filter(|x| !old_paths.contains(x));
+    let removed = old_paths.iter().filter(|x| !new_paths.contains(x));
+    added
+        .map(|x| (x, true))
+        .chain(removed.map(|x| (x, false)))
+        .map(|((kind, path), added)| PathDiff {
+            kind: *kind,
+            path: path.clone(),
+            added,
+        })
+        .collect()
+}
+
+fn diff_edges(old: &Data, new: &Data) -> Vec<EdgeDiff> {
+    let variables = |data: &Data, edge: EdgeKey| -> Vec<(&str, String)> {
+        RULE_VARS
+            .into_iter()
+            .map(|name| (name, data.evaluate_edge(edge, name)))
+            .filter(|x| !x.1.is_empty())
+            .collect()
+    };
+    let edges = match_edges(old, new);
+    let mut ret = Vec::new();
+    for (old_key, new_key) in edges.matched {
+        let rule = diff_rule(old, old_key, new, new_key);
+        let paths = diff_paths(old, old_key, new, new_key);
+        let variables = diff_values(variables(old, old_key), variables(new, new_key));
+        if rule.is_some() || !paths.is_empty() || !variables.is_empty() {
+            ret.push(EdgeDiff {
+                outputs: edge_outputs(new, new_key),
+                kind: DiffKind::Changed,
+                rule,
+                paths,
+                variables,
+            });
+        }
+    }
+    let added = edges.added.into_iter().map(|x| (new, x, DiffKind::Added));
+    let removed = edges
+        .removed
+        .into_iter()
+        .map(|x| (old, x, DiffKind::Removed));
+    for (data, key, kind) in added.chain(removed) {
+        ret.push(EdgeDiff {
+            outputs: edge_outputs(data, key),
+            kind,
+            rule: None,
+            paths: Vec::new(),
+            variables: Vec::new(),
+        });
+    }
+    ret
+}
+
+fn diff_variables(old: &Data, new: &Data) -> Vec<VariableDiff> {
+    fn scopes<'a>(data: &'a Data) -> BTreeMap<Option<String>, Vec<(&'a str, String)>> {
+        data.scopes
+            .iter()
+            .map(|(key, scope)| {
+                let vars = scope.vars.iter();
+                let vars = vars.map(|x| (x.name.elem.as_str(), x.value.elem.clone()));
+                (scope_name(data, key), vars.collect())
+            })
+            .collect()
+    }
+    let (old, mut new) = (scopes(old), scopes(new));
+    let mut ret = Vec::new();
+    for (scope, old) in old {
+        let new = new.remove(&scope).unwrap_or_default();
+        for value in diff_values(old, new) {
+            let scope = scope.clone();
+            ret.push(VariableDiff { scope, value });
+        }
+    }
+    for (scope, new) in new {
+        for value in diff_values(Vec::new(), new) {
+            let scope = scope.clone();
+            ret.push(VariableDiff { scope, value });
+        }
+    }
+    ret
+}
+
+fn path_kind_name(kind: PathKind) -> &'static str {
+    match kind {
+        PathKind::Out => "output",
+        PathKind::ImplicitOut => "implicit output",
+        PathKind::In => "input",
+        PathKind::ImplicitIn => "implicit input",
+        PathKind::OrderOnlyIn => "order-only input",
+        PathKind::Validation => "validation",
+    }
+}
+
+fn sign(kind: DiffKind) -> char {
+    match kind {
+        DiffKind::Added => '+',
+        DiffKind::Removed => '-',
+        DiffKind::Changed => '~',
+    }
+}
+
+impl fmt::Display for ValueDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.old, &self.new) {
+            (None, Some(new)) => write!(f, "+ {} = {}", self.name, new),
+            (Some(old), None) => write!(f, "- {} = {}", self.name, old),
+            (old, new) => write!(
+                f,
+                "~ {}: {} -> {}",
+                self.name,
+                old.as_deref().unwrap_or_default(),
+                new.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let in_scope = |scope: &Option<String>| match scope {
+            Some(x) => format!(" (in {})", x),
+            None => String::new(),
+        };
+        for rule in &self.rules {
+            let scope = in_scope(&rule.scope);
+            writeln!(f, "{} rule {}{}", sign(rule.kind), rule.name, scope)?;
+            for binding in &rule.bindings {
+                writeln!(f, "    {}", binding)?;
+            }
+        }
+        for edge in &self.edges {
+            writeln!(f, "{} build {}", sign(edge.kind), edge.outputs.join(" "))?;
+            for value in edge.rule.iter().chain(&edge.variables) {
+                writeln!(f, "    {}", value)?;
+            }
+            for path in &edge.paths {
+                let sign = if path.added { '+' } else { '-' };
+                writeln!(
+                    f,
+                    "    {} {} {}",
+                    sign,
+                    path_kind_name(path.kind),
+                    path.path
+                )?;
+            }
+        }
+        for variable in &self.variables {
+            let value = ValueDiff {
+                name: format!("${}", variable.value.name),
+                ..variable.value.clone()
+            };
+            writeln!(f, "{}{}", value, in_scope(&variable.scope))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{Data, EdgeKey, NodeKey, PathKind, RuleKey};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct DotOptions {
+    /// Draws `phony` edges as plain arrows from their inputs to their
+    /// outputs, instead of a node of their own.
+    pub collapse_phony: bool,
+    /// Gives every rule its own color.
+    pub color_by_rule: bool,
+    /// Only follows dependencies this far from the targets.
+    pub max_depth: Option<usize>,
+    /// Draws order-only inputs dashed instead of dotted.
+    pub dashed_order_only: bool,
+}
+
+const COLORS: [&str; 12] = [
+    "blue",
+    "red",
+    "darkgreen",
+    "darkorange",
+    "purple",
+    "brown",
+    "deeppink",
+    "cyan4",
+    "gold3",
+    "navy",
+    "olivedrab",
+    "firebrick",
+];
+
+impl Data<'_> {
+    /// The graph in Graphviz DOT format, as `ninja -t graph` prints it.
+    ///
+    /// Only what `targets` depend on is drawn; without targets, the whole
+    /// graph is, starting from the nodes nothing depends on and from the
+    /// dependency cycles.
+    pub fn to_dot(&self, targets: &[NodeKey], options: &DotOptions) -> String {
+        let mut dot = Dot {
+            data: self,
+            options,
+            out: String::new(),
+            node_ids: HashMap::new(),
+            node_depths: HashMap::new(),
+            edges: HashMap::new(),
+            colors: HashMap::new(),
+        };
+        dot.out += "digraph ninja {\n";
+        dot.out += "rankdir=\"LR\"\n";
+        dot.out += "node [fontsize=10, shape=box, height=0.25]\n";
+        dot.out += "edge [fontsize=10]\n";
+
+        if targets.is_empty() {
+            let roots = self.nodes.iter().filter(|(_, x)| x.out_edges.is_empty());
+            for (key, _) in roots {
+                dot.add_target(key, 0);
+            }
+            // What only cycles depend on, starting from the cycles.
+            for cycle in self.cycles() {
+                for step in cycle.steps {
+                    if !dot.node_depths.contains_key(&step.node) {
+                        dot.add_target(step.node, 0);
+                    }
+                }
+            }
+        } else {
+            for &key in targets {
+                dot.add_target(key, 0);
+            }
+        }
+
+        dot.out += "}\n";
+        dot.out
+    }
+}
+
+struct Dot<'a, 'x> {
+    data: &'a Data<'x>,
+    options: &'a DotOptions,
+    out: String,
+    node_ids: HashMap<NodeKey, usize>,
+    /// The smallest depth each node was reached at.
+    node_depths: HashMap<NodeKey, usize>,
+    /// Visited edges with the ids of their DOT nodes.
+    edges: HashMap<EdgeKey, usize>,
+    colors: HashMap<RuleKey, &'static str>,
+}
+impl Dot<'_, '_> {
+    fn node_id(&mut self, key: NodeKey) -> String {
+        let n = self.node_ids.len();
+        format!("n{}", self.node_ids.entry(key).or_insert(n))
+    }
+
+    /// Draws `key` and what it depends on, depth first like ninja; with a
+    /// worklist rather than recursion, as chains of dependencies can be
+    /// longer than the stack allows.
+    fn add_target(&mut self, key: NodeKey, depth: usize) {
+        let mut stack = vec![(key, depth)];
+        while let Some((key, depth)) = stack.pop() {
+            match self.node_depths.get(&key) {
+                Some(&old) if old <= depth => continue,
+                Some(_) => {}
+                None => {
+                    let id = self.node_id(key);
+                    let path = self.data.nodes[key].path.replace('\\', "/");
+                    self.out += &format!("\"{}\" [label=\"{}\"]\n", id, escape(&path));
+                }
+            }
+            self.node_depths.insert(key, depth);
+            if self.options.max_depth.is_some_and(|x| depth >= x) {
+                continue;
+            }
+            let Some(edge_key) = self.data.nodes[key].in_edge else {
+                continue;
+            };
+
+            let edge = &self.data.edges[edge_key];
+            let inputs: Vec<_> = [PathKind::In, PathKind::ImplicitIn, PathKind::OrderOnlyIn]
+                .into_iter()
+                .flat_map(|kind| edge.paths(kind).iter().map(move |x| (kind, x.elem)))
+                .collect();
+            if !self.edges.contains_key(&edge_key) {
+                let id = self.edges.len();
+                self.edges.insert(edge_key, id);
+                self.add_edge(edge_key, id, &inputs);
+            }
+
+            // Reversed, so that the first input is drawn first.
+            stack.extend(inputs.iter().rev().map(|&(_, input)| (input, depth + 1)));
+        }
+    }
+
+    fn add_edge(&mut self, key: EdgeKey, id: usize, inputs: &[(PathKind, NodeKey)]) {
+        let data = self.data;
+        let edge = &data.edges[key];
+        let rule = &data.rules[edge.rule];
+        let outputs: Vec<_> = [PathKind::Out, PathKind::ImplicitOut]
+            .into_iter()
+            .flat_map(|kind| edge.paths(kind))
+            .map(|x| x.elem)
+            .collect();
+        let color = match self.options.color_by_rule {
+            true => {
+                let n = self.colors.len();
+                let color = *self
+                    .colors
+                    .entry(edge.rule)
+                    .or_insert(COLORS[n % COLORS.len()]);
+                format!(" color=\"{}\"", color)
+            }
+            false => String::new(),
+        };
+        let options = self.options;
+        let style = |kind| match kind {
+            PathKind::OrderOnlyIn if options.dashed_order_only => " style=dashed",
+            PathKind::OrderOnlyIn => " style=dotted",
+            _ => "",
+        };
+        let attributes = |list: &str| match list.trim_start() {
+            "" => String::new(),
+            list => format!(" [{}]", list),
+        };
+
+        if self.options.collapse_phony && rule.file.is_none() {
+            for &output in &outputs {
+                for &(kind, input) in inputs {
+                    let (input, output) = (self.node_id(input), self.node_id(output));
+                    let list = attributes(&format!("{}{}", style(kind), color));
+                    self.out += &format!("\"{}\" -> \"{}\"{}\n", input, output, list);
+                }
+            }
+        } else if let ([(_, input)], [output]) = (inputs, outputs.as_slice()) {
+            let (input, output) = (self.node_id(*input), self.node_id(*output));
+            // The space before the label is how ninja writes it.
+            self.out += &format!(
+                "\"{}\" -> \"{}\" [label=\" {}\"{}]\n",
+                input,
+                output,
+                escape(rule.name.elem),
+                color
+            );
+        } else {
+            let shape = format!("shape=ellipse{}", color.replace(" color", ", color"));
+            self.out += &format!(
+                "\"e{}\" [label=\"{}\", {}]\n",
+                id,
+                escape(rule.name.elem),
+                shape
+            );
+            for &output in &outputs {
+                let output = self.node_id(output);
+                self.out += &format!("\"e{}\" -> \"{}\"{}\n", id, output, attributes(&color));
+            }
+            for &(kind, input) in inputs {
+                let input = self.node_id(input);
+                self.out += &format!(
+                    "\"{}\" -> \"e{}\" [arrowhead=none{}{}]\n",
+                    input,
+                    id,
+                    style(kind),
+                    color
+                );
+            }
+        }
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DotOptions, Ninja};
+    use fs_err as fs;
+    use std::fmt::Write;
+
+    #[test]
+    fn long_chains() {
+        let dir = std::env::temp_dir().join(format!("ninja_editor_dot_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.ninja");
+        let mut text = "rule cp\n  command = cp $in $out\n".to_string();
+        for n in 1..100_000 {
+            writeln!(text, "build {}: cp {}", n, n - 1).unwrap();
+        }
+        fs::write(&path, text).unwrap();
+        let ninja = Ninja::load(&path);
+
+        let dot = ninja.data().to_dot(&[], &DotOptions::default());
+        assert_eq!(dot.matches("[label=\" cp\"]").count(), 99_999);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    lexer::LOC_INVALID, Data, Diagnostic, EdgeKey, Location, Ninja, NodeKey, ValidationError,
+};
+use std::collections::HashSet;
+
+/// A path generated by more than one `build` statement, which ninja refuses
+/// unless run with `-w dupbuild=warn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DuplicateOutput {
+    pub node: NodeKey,
+    /// The edge generating the node first, which is its [`Node::in_edge`].
+    ///
+    /// [`Node::in_edge`]: crate::Node::in_edge
+    pub first: EdgeKey,
+    /// Where `first` writes the path; `None` if a `Data` building function
+    /// added it.
+    pub first_loc: Option<Location>,
+    pub duplicate: EdgeKey,
+    pub duplicate_loc: Option<Location>,
+}
+
+impl Data<'_> {
+    /// Every output of an edge that an earlier edge, or the same edge, also
+    /// generates. Only manifests loaded with [`DupBuild::Allow`] have these.
+    ///
+    /// [`DupBuild::Allow`]: crate::DupBuild::Allow
+    pub fn duplicate_outputs(&self) -> Vec<DuplicateOutput> {
+        let mut ret = Vec::new();
+        // Nodes whose first occurrence in their `in_edge` was seen.
+        let mut seen = HashSet::new();
+        let outputs = |edge: EdgeKey| {
+            let edge = &self.edges[edge];
+            edge.outs.iter().chain(&edge.implicit_outs).map(|x| {
+                let loc = Some(x.loc()).filter(|&x| x != LOC_INVALID);
+                (x.elem, loc)
+            })
+        };
+        for key in self.edges.keys() {
+            for (node, loc) in outputs(key) {
+                let Some(in_edge) = self.nodes[node].in_edge else {
+                    continue;
+                };
+                if in_edge == key && seen.insert(node) {
+                    continue;
+                }
+                let first_loc = outputs(in_edge)
+                    .find(|&(x, _)| x == node)
+                    .and_then(|(_, loc)| loc);
+                ret.push(DuplicateOutput {
+                    node,
+                    first: in_edge,
+                    first_loc,
+                    duplicate: key,
+                    duplicate_loc: loc,
+                });
+            }
+        }
+        ret
+    }
+}
+
+impl Ninja {
+    /// Fails like ninja does for each of [`Data::duplicate_outputs`], with a
+    /// diagnostic at the duplicate and one at the first output.
+    pub fn check_duplicate_outputs(&self) -> Result<(), ValidationError> {
+        let mut diagnostics = Vec::new();
+        for i in self.data.duplicate_outputs() {
+            let path = &self.data.nodes[i.node].path;
+            let diagnostic = |loc: Option<Location>, message: String| match loc {
+                Some(loc) => Diagnostic::at(&self.sm, loc, message),
+                None => Diagnostic::for_file(self.root_path(), message),
+            };
+            diagnostics.push(diagnostic(
+                i.duplicate_loc,
+                format!("multiple rules generate `{}`", path),
+            ));
+            diagnostics.push(diagnostic(
+                i.first_loc,
+                format!("`{}` is first generated here", path),
+            ));
+        }
+        match diagnostics.is_empty() {
+            true => Ok(()),
+            false => Err(ValidationError { diagnostics }),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{
+    diagnostic::{Diagnostic, ParseError},
+    parser::{parse_dyndep, DyndepStatement},
+    Data, EdgeKey, Ninja, NodeKey, PathKind, L,
+};
+use fs_err as fs;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+/// What a dyndep file adds to the `build` statement of one of its edges.
+#[derive(Debug, Clone)]
+pub struct Dyndep {
+    /// The dyndep file.
+    pub file: NodeKey,
+    pub implicit_outs: Vec<NodeKey>,
+    pub implicit_ins: Vec<NodeKey>,
+    pub restat: bool,
+}
+
+impl Data<'_> {
+    /// The dyndep file of `edge`: the input named by its `dyndep` variable.
+    pub fn dyndep_file(&self, edge: EdgeKey) -> Option<NodeKey> {
+        let path = self.evaluate_edge_unescaped(edge, "dyndep");
+        if path.is_empty() {
+            return None;
+        }
+        let edge = &self.edges[edge];
+        [PathKind::In, PathKind::ImplicitIn, PathKind::OrderOnlyIn]
+            .into_iter()
+            .flat_map(|kind| edge.paths(kind))
+            .map(|x| x.elem)
+            .find(|&x| self.nodes[x].path == path)
+    }
+
+    /// The edges having `file` as their dyndep file.
+    pub fn dyndep_edges(&self, file: NodeKey) -> Vec<EdgeKey> {
+        let mut edges = self.nodes[file].out_edges.clone();
+        edges.dedup();
+        edges.retain(|&x| self.dyndep_file(x) == Some(file));
+        edges
+    }
+
+    /// Parses `text` as the content of the dyndep file `file` and adds its
+    /// paths to the edges using it, replacing what a previous load added.
+    ///
+    /// Like ninja, this fails if a statement is for an edge that doesn't use
+    /// `file`, or if an edge using it has no statement. Nothing is changed on
+    /// failure.
+    pub fn load_dyndep(&mut self, file: NodeKey, text: &str) -> Result<(), Diagnostic> {
+        let path = self.nodes[file].path.clone();
+        let statements = parse_dyndep(&format!("{}\0", text))
+            .map_err(|e| Diagnostic::in_text(&path, text, e))?;
+        let edges = self
+            .dyndep_statements(file, &statements)
+            .map_err(|e| Diagnostic::in_text(&path, text, e))?;
+        for &edge in &self.dyndep_edges(file) {
+            if !edges.contains_key(&edge) {
+                let output = self.edges[edge].outputs().next();
+                let output = output.map_or("", |x| self.nodes[x].path.as_str());
+                return Err(Diagnostic::for_file(
+                    &path,
+                    format!("`{}` not mentioned in its dyndep file `{}`", output, path),
+                ));
+            }
+        }
+
+        for &edge in edges.keys() {
+            self.unload_dyndep(edge);
+        }
+        for (edge, statement) in edges {
+            let mut dyndep = Dyndep {
+                file,
+                implicit_outs: Vec::new(),
+                implicit_ins: Vec::new(),
+                restat: statement.restat,
+            };
+            for path in &statement.implicit_outs {
+                let node = self.insert_node(L::synthetic(path.elem.clone()));
+                self.nodes[node].in_edge = Some(edge);
+                dyndep.implicit_outs.push(node);
+            }
+            for path in &statement.implicit_ins {
+                let node = self.insert_node(L::synthetic(path.elem.clone()));
+                self.nodes[node].out_edges.push(edge);
+                dyndep.implicit_ins.push(node);
+            }
+            self.edges[edge].dyndep = Some(dyndep);
+        }
+        Ok(())
+    }
+
+    /// Matches the statements of the dyndep file `file` with their edges.
+    fn dyndep_statements<'a>(
+        &self,
+        file: NodeKey,
+        statements: &'a [DyndepStatement],
+    ) -> Result<HashMap<EdgeKey, &'a DyndepStatement>, ParseError> {
+        let file_path = &self.nodes[file].path;
+        let mut edges = HashMap::new();
+        for statement in statements {
+            let output = &statement.output;
+            let edge = self.node(&output.elem).and_then(|node| {
+                let edge = self.nodes[node].in_edge?;
+                let edge_ref = &self.edges[edge];
+                let mut outs = edge_ref.outs.iter().chain(&edge_ref.implicit_outs);
+                outs.any(|x| x.elem == node).then_some(edge)
+            });
+            let Some(edge) = edge else {
+                return Err(ParseError::new(
+                    format!("no build statement exists for `{}`", output.elem),
+                    output.loc,
+                ));
+            };
+            if self.dyndep_file(edge) != Some(file) {
+                return Err(ParseError::new(
+                    format!(
+                        "dyndep file `{}` mentions output `{}` whose build statement \
+                         does not have a dyndep binding for the file",
+                        file_path, output.elem
+                    ),
+                    output.loc,
+                ));
+            }
+            if edges.insert(edge, statement).is_some() {
+                return Err(ParseError::new(
+                    format!("multiple statements for `{}`", output.elem),
+                    output.loc,
+                ));
+            }
+        }
+
+        let mut outputs = HashSet::new();
+        for path in statements.iter().flat_map(|x| &x.implicit_outs) {
+            let generated = self.node(&path.elem).and_then(|node| {
+                let edge = self.nodes[node].in_edge?;
+                // Outputs added by a previous load of this file are replaced.
+                let reloaded = self.edges[edge]
+                    .dyndep
+                    .as_ref()
+                    .is_some_and(|x| x.file == file && x.implicit_outs.contains(&node));
+                (!reloaded).then_some(edge)
+            });
+            if generated.is_some() || !outputs.insert(path.elem.as_str()) {
+                return Err(ParseError::new(
+                    format!("multiple rules generate `{}`", path.elem),
+                    path.loc,
+                ));
+            }
+        }
+        Ok(edges)
+    }
+
+    /// Removes what a dyndep file added to `edge`.
+    fn unload_dyndep(&mut self, edge: EdgeKey) {
+        let Some(dyndep) = self.edges[edge].dyndep.take() else {
+            return;
+        };
+        for node in dyndep.implicit_outs {
+            self.nodes[node].in_edge = None;
+        }
+        for node in dyndep.implicit_ins {
+            let out_edges = &mut self.nodes[node].out_edges;
+            if let Some(n) = out_edges.iter().position(|&x| x == edge) {
+                out_edges.remove(n);
+            }
+        }
+    }
+}
+
+impl Ninja {
+    /// Loads the dyndep files of the manifest that exist, see
+    /// [`Data::load_dyndep`]; returns how many were loaded. Dyndep files are
+    /// usually generated during the build, so missing ones are skipped.
+    pub fn load_dyndeps(&mut self) -> Result<usize, Diagnostic> {
+        let mut files: Vec<_> = self
+            .data
+            .edges
+            .keys()
+            .filter_map(|x| self.data.dyndep_file(x))
+            .collect();
+        files.sort();
+        files.dedup();
+
+        let mut count = 0;
+        for file in files {
+            let path = Path::new(&self.data.nodes[file].path);
+            if !path.exists() {
+                continue;
+            }
+            let text =
+                fs::read_to_string(path).map_err(|e| Diagnostic::for_file(path, e.to_string()))?;
+            self.data.load_dyndep(file, &text)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
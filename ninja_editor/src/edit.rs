@@ -0,0 +1,372 @@
+use crate::{
+    lexer::{Location, LOC_INVALID},
+    ChangeList, Data, EdgeKey, EscapeError, EvalString, FileKey, FileKind, Ninja, NodeKey,
+    PathKind, RuleKey, ScopeKey, SourceId,
+};
+use std::path::Path;
+
+/// The name, name location and value location of the bindings of a rule or
+/// `build` statement.
+type Bindings<'a> = Vec<(&'a str, Location, Location)>;
+
+impl<'x> ChangeList<'x> {
+    /// Sets the binding `name` of `edge`, replacing its value or adding a
+    /// line after its other bindings.
+    pub fn set_edge_binding(
+        &mut self,
+        edge: EdgeKey,
+        name: &str,
+        value: &EvalString,
+    ) -> Result<(), EscapeError> {
+        self.set_edge_binding_text(edge, name, &value.to_value()?);
+        Ok(())
+    }
+
+    /// [`ChangeList::set_edge_binding`] with the value as it's written.
+    pub(crate) fn set_edge_binding_text(&mut self, edge: EdgeKey, name: &str, value: &str) {
+        let ninja = self.ninja();
+        let bindings = edge_bindings(ninja, edge);
+        let end = edge_line_end(ninja, edge);
+        self.set_binding(&bindings, end, name, value);
+    }
+
+    /// Removes the line of the binding `name` of `edge`, if it has one.
+    pub fn remove_edge_binding(&mut self, edge: EdgeKey, name: &str) {
+        let bindings = edge_bindings(self.ninja(), edge);
+        self.remove_binding(&bindings, name);
+    }
+
+    /// Sets the binding `name` of `rule`, replacing its value or adding a
+    /// line after its other bindings.
+    pub fn set_rule_binding(
+        &mut self,
+        rule: RuleKey,
+        name: &str,
+        value: &EvalString,
+    ) -> Result<(), EscapeError> {
+        let ninja = self.ninja();
+        let bindings = rule_bindings(ninja, rule);
+        let name_loc = ninja.data.rules[rule].name.loc();
+        let end = bindings.last().map_or(name_loc, |x| x.2);
+        self.set_binding(&bindings, end, name, &value.to_value()?);
+        Ok(())
+    }
+
+    /// Removes the line of the binding `name` of `rule`, if it has one.
+    pub fn remove_rule_binding(&mut self, rule: RuleKey, name: &str) {
+        let bindings = rule_bindings(self.ninja(), rule);
+        self.remove_binding(&bindings, name);
+    }
+
+    /// Sets the top level variable `name` of `scope`: replaces the value of
+    /// its last assignment, or adds one at the end of the file of the scope.
+    pub fn set_variable(
+        &mut self,
+        scope: ScopeKey,
+        name: &str,
+        value: &EvalString,
+    ) -> Result<(), EscapeError> {
+        self.set_variable_text(scope, name, &value.to_value()?);
+        Ok(())
+    }
+
+    /// [`ChangeList::set_variable`] with the value as it's written.
+    pub(crate) fn set_variable_text(&mut self, scope: ScopeKey, name: &str, value: &str) {
+        let ninja = self.ninja();
+        match ninja.data.scopes[scope].var(name) {
+            Some(var) if var.value.loc() != LOC_INVALID => {
+                let loc = trim_line_break(ninja, var.value.loc());
+                self.replace(loc, value.to_string());
+            }
+            _ => {
+                if let Some(file) = scope_file(&ninja.data, scope) {
+                    self.append(file, format!("{} = {}\n", name, value));
+                }
+            }
+        }
+    }
+
+    /// Removes the last assignment of the top level variable `name` of
+    /// `scope`, if it has one.
+    pub fn remove_variable(&mut self, scope: ScopeKey, name: &str) {
+        let ninja = self.ninja();
+        if let Some(var) = ninja.data.scopes[scope].var(name) {
+            let bindings = vec![(name, var.name.loc(), var.value.loc())];
+            self.remove_binding(&bindings, name);
+        }
+    }
+
+    /// Adds `path` to `edge`, after its other paths of that kind. The paths
+    /// added to an edge are written together on commit, in the order of
+    /// [`PathKind::ALL`].
+    pub fn add_edge_path(
+        &mut self,
+        edge: EdgeKey,
+        kind: PathKind,
+        path: &str,
+    ) -> Result<(), EscapeError> {
+        let text = EvalString::literal(path).to_path()?;
+        self.added_paths.push((edge, kind, text));
+        Ok(())
+    }
+
+    /// Removes every occurrence of `node` as a path of that kind of `edge`,
+    /// and the separator before the paths of that kind if none are left.
+    pub fn remove_edge_path(&mut self, edge: EdgeKey, kind: PathKind, node: NodeKey) {
+        let ninja = self.ninja();
+        let paths = ninja.data.edges[edge].paths(kind);
+        let locs: Vec<_> = paths
+            .iter()
+            .filter(|x| x.elem == node && x.loc() != LOC_INVALID)
+            .map(|x| x.loc())
+            .collect();
+        let Some(&first) = locs.first() else {
+            return;
+        };
+        let text = ninja.sm.get(first.source_id).text();
+        // Take the space before each path with it.
+        let space_before = |start: usize| text[..start].trim_end_matches(' ').len();
+        let separator = match kind {
+            PathKind::ImplicitOut | PathKind::ImplicitIn => "|",
+            PathKind::OrderOnlyIn => "||",
+            PathKind::Validation => "|@",
+            PathKind::Out | PathKind::In => "",
+        };
+        if locs.len() == paths.len() && !separator.is_empty() {
+            let before = text[..first.start].trim_end_matches(' ');
+            if let Some(rest) = before.strip_suffix(separator) {
+                let loc = Location {
+                    start: space_before(rest.len()),
+                    stop: locs.last().unwrap().stop,
+                    ..first
+                };
+                self.replace(loc, String::new());
+                return;
+            }
+        }
+        for loc in locs {
+            self.replace(
+                Location {
+                    start: space_before(loc.start),
+                    ..loc
+                },
+                String::new(),
+            );
+        }
+    }
+
+    /// Removes the `build` statement of `edge` with its bindings.
+    pub fn remove_edge(&mut self, edge: EdgeKey) {
+        let ninja = self.ninja();
+        let edge_ref = &ninja.data.edges[edge];
+        if edge_ref.loc == LOC_INVALID {
+            return;
+        }
+        self.remove_lines(edge_ref.loc, edge_line_end(ninja, edge));
+    }
+
+    /// Removes the `rule` statement of `rule` with its bindings. The edges
+    /// using it are left as they are.
+    pub fn remove_rule(&mut self, rule: RuleKey) {
+        let ninja = self.ninja();
+        let name = ninja.data.rules[rule].name.loc();
+        if name == LOC_INVALID {
+            return;
+        }
+        let end = rule_bindings(ninja, rule).last().map_or(name, |x| x.2);
+        self.remove_lines(name, end);
+    }
+
+    /// Adds `text` at the end of `file` on commit.
+    pub(crate) fn append(&mut self, file: FileKey, text: String) {
+        self.appended.push((file, text));
+    }
+
+    /// The text changes of [`ChangeList::add_edge_path`] and
+    /// [`ChangeList::append`].
+    pub(crate) fn pending_changes(&self) -> Vec<(Location, String)> {
+        let ninja = self.ninja();
+        let mut changes = Vec::new();
+
+        let mut edges: Vec<_> = self.added_paths.iter().map(|x| x.0).collect();
+        edges.sort();
+        edges.dedup();
+        for edge in edges {
+            let edge_ref = &ninja.data.edges[edge];
+            if edge_ref.loc == LOC_INVALID {
+                continue;
+            }
+            // Added paths go after the last path of their kind, or after the
+            // paths before them with their separator.
+            let mut end = edge_ref.rule_loc.stop;
+            for kind in PathKind::ALL {
+                let last = edge_ref.paths(kind).last().map(|x| x.loc().stop);
+                let anchor = match kind {
+                    PathKind::Out => last.unwrap_or(edge_ref.loc.stop),
+                    PathKind::ImplicitOut => last
+                        .or(edge_ref.outs.last().map(|x| x.loc().stop))
+                        .unwrap_or(edge_ref.loc.stop),
+                    PathKind::In => last.unwrap_or(edge_ref.rule_loc.stop),
+                    _ => last.unwrap_or(end),
+                };
+                if !kind.is_output() {
+                    end = end.max(anchor);
+                }
+                let added: Vec<_> = self
+                    .added_paths
+                    .iter()
+                    .filter(|x| x.0 == edge && x.1 == kind)
+                    .collect();
+                if added.is_empty() {
+                    continue;
+                }
+                let mut text = match (last, kind) {
+                    (Some(_), _) | (None, PathKind::Out | PathKind::In) => String::new(),
+                    (None, PathKind::ImplicitOut | PathKind::ImplicitIn) => " |".to_string(),
+                    (None, PathKind::OrderOnlyIn) => " ||".to_string(),
+                    (None, PathKind::Validation) => " |@".to_string(),
+                };
+                for (_, _, path) in added {
+                    text.push(' ');
+                    text += path;
+                }
+                let loc = Location {
+                    start: anchor,
+                    stop: anchor,
+                    ..edge_ref.loc
+                };
+                changes.push((loc, text));
+            }
+        }
+
+        for (file, text) in &self.appended {
+            let Some(source) = file_source(ninja, *file) else {
+                continue;
+            };
+            let source_text = ninja.sm.get(source).text();
+            let len = source_text.len();
+            let text = match source_text.is_empty() || source_text.ends_with('\n') {
+                true => text.clone(),
+                false => format!("\n{}", text),
+            };
+            let loc = Location {
+                start: len,
+                stop: len,
+                source_id: source,
+            };
+            changes.push((loc, text));
+        }
+        changes
+    }
+
+    /// Replaces the value of the binding `name`, or adds it after `end`.
+    fn set_binding(&mut self, bindings: &Bindings, end: Location, name: &str, value: &str) {
+        let ninja = self.ninja();
+        match bindings.iter().rev().find(|x| x.0 == name) {
+            Some(&(_, _, value_loc)) => {
+                let loc = trim_line_break(ninja, value_loc);
+                self.replace(loc, value.to_string());
+            }
+            None => {
+                let end = trim_line_break(ninja, end);
+                let loc = Location {
+                    start: end.stop,
+                    ..end
+                };
+                self.replace(loc, format!("\n  {} = {}", name, value));
+            }
+        }
+    }
+
+    fn remove_binding(&mut self, bindings: &Bindings, name: &str) {
+        if let Some(&(_, name_loc, value_loc)) = bindings.iter().rev().find(|x| x.0 == name) {
+            if name_loc != LOC_INVALID {
+                self.remove_lines(name_loc, value_loc);
+            }
+        }
+    }
+
+    /// Removes the lines from the one of `start` to the one of `end`.
+    fn remove_lines(&mut self, start: Location, end: Location) {
+        let text = self.ninja().sm.get(start.source_id).text();
+        let line_start = text[..start.start].rfind('\n').map_or(0, |x| x + 1);
+        let line_end = match text[..end.stop].ends_with('\n') {
+            true => end.stop,
+            false => text[end.stop..]
+                .find('\n')
+                .map_or(text.len(), |x| end.stop + x + 1),
+        };
+        let loc = Location {
+            start: line_start,
+            stop: line_end,
+            ..start
+        };
+        self.replace(loc, String::new());
+    }
+}
+
+fn edge_bindings(ninja: &Ninja, edge: EdgeKey) -> Bindings<'_> {
+    let bindings = ninja.data.edges[edge].bindings.iter();
+    bindings
+        .filter(|x| x.name.loc() != LOC_INVALID)
+        .map(|x| (x.name.elem.as_str(), x.name.loc(), x.value.loc()))
+        .collect()
+}
+
+fn rule_bindings(ninja: &Ninja, rule: RuleKey) -> Bindings<'_> {
+    let bindings = ninja.data.rules[rule].bindings.iter();
+    bindings
+        .filter(|x| x.name.loc() != LOC_INVALID)
+        .map(|x| (x.name.elem.as_str(), x.name.loc(), x.value.loc()))
+        .collect()
+}
+
+/// Where the last binding of `edge` ends, or its `build` line.
+fn edge_line_end(ninja: &Ninja, key: EdgeKey) -> Location {
+    if let Some(&(_, _, value)) = edge_bindings(ninja, key).last() {
+        return value;
+    }
+    let edge = &ninja.data.edges[key];
+    let stop = edge
+        .all_paths()
+        .map(|(_, x)| x.loc())
+        .filter(|&x| x != LOC_INVALID)
+        .map(|x| x.stop)
+        .chain([edge.rule_loc.stop])
+        .max()
+        .unwrap();
+    Location {
+        start: stop,
+        stop,
+        ..edge.loc
+    }
+}
+
+/// The root or `subninja` file of `scope`.
+pub(crate) fn scope_file(data: &Data, scope: ScopeKey) -> Option<FileKey> {
+    data.files
+        .iter()
+        .find(|(_, x)| x.scope == scope && x.kind != FileKind::Include)
+        .map(|(key, _)| key)
+}
+
+/// The source of a manifest file.
+pub(crate) fn file_source(ninja: &Ninja, file: FileKey) -> Option<SourceId> {
+    let path = Path::new(&ninja.data.files[file].path.elem);
+    ninja
+        .sm
+        .sources
+        .iter()
+        .find(|x| x.path == path)
+        .map(|x| x.id)
+}
+
+/// `loc` without the line break ending a value.
+pub(crate) fn trim_line_break(ninja: &Ninja, loc: Location) -> Location {
+    let text = ninja.sm.get(loc.source_id).str_loc(loc);
+    let trimmed = text.trim_end_matches(['\r', '\n']);
+    Location {
+        stop: loc.start + trimmed.len(),
+        ..loc
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    diagnostic::{Diagnostic, ParseError},
+    lexer::{Lexer, Location, LOC_INVALID},
+};
+use std::fmt;
+
+/// A string with variable references, as written in a manifest.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct EvalString {
+    parts: Vec<EvalPart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EvalPart {
+    Literal(String),
+    Var(String),
+}
+
+/// A character that can't be written in a manifest at that position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscapeError {
+    pub text: String,
+    pub c: char,
+}
+impl fmt::Display for EscapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` contains {:?}, which can't be escaped",
+            self.text, self.c
+        )
+    }
+}
+impl std::error::Error for EscapeError {}
+
+impl EvalString {
+    pub fn literal<S: Into<String>>(text: S) -> EvalString {
+        let mut ret = EvalString::default();
+        ret.push_literal(&text.into());
+        ret
+    }
+    pub fn push_literal(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match self.parts.last_mut() {
+            Some(EvalPart::Literal(last)) => last.push_str(text),
+            _ => self.parts.push(EvalPart::Literal(text.to_string())),
+        }
+    }
+    pub fn push_var(&mut self, name: &str) {
+        self.parts.push(EvalPart::Var(name.to_string()));
+    }
+    pub fn parts(&self) -> &[EvalPart] {
+        &self.parts
+    }
+    /// The names of the variables it evaluates, in order.
+    pub(crate) fn variables(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|x| match x {
+            EvalPart::Var(name) => Some(name.as_str()),
+            EvalPart::Literal(_) => None,
+        })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+    /// The literal text, if there are no variable references.
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [] => Some(""),
+            [EvalPart::Literal(x)] => Some(x),
+            _ => None,
+        }
+    }
+    /// Replaces every variable with what `lookup` returns for it.
+    pub fn evaluate<F: FnMut(&str) -> String>(&self, mut lookup: F) -> String {
+        let mut ret = String::new();
+        for i in &self.parts {
+            match i {
+                EvalPart::Literal(x) => ret += x,
+                EvalPart::Var(x) => ret += &lookup(x),
+            }
+        }
+        ret
+    }
+    /// Parses the text of a value as it's written after `name =`, with `$`
+    /// escapes and variable references. The diagnostic has no path and
+    /// points into `text`.
+    pub fn parse_value(text: &str) -> Result<EvalString, Diagnostic> {
+        let padded = format!("{}\n\0", text);
+        let mut lexer = Lexer::new(&padded, LOC_INVALID.source_id);
+        let error = |e| Diagnostic::in_text("", text, e);
+        let value = lexer.read_var_value().map_err(error)?;
+        if lexer.offset() != padded.len() - 1 {
+            let loc = Location {
+                start: lexer.offset(),
+                stop: lexer.offset(),
+                source_id: LOC_INVALID.source_id,
+            };
+            return Err(error(ParseError::new("unexpected line break", loc)));
+        }
+        Ok(value.elem)
+    }
+    /// The text to write after `name =`.
+    pub fn to_value(&self) -> Result<String, EscapeError> {
+        self.write(false)
+    }
+    /// The text to write for a path in a `build`, `default`, `include` or
+    /// `subninja` statement.
+    pub fn to_path(&self) -> Result<String, EscapeError> {
+        self.write(true)
+    }
+    fn write(&self, path: bool) -> Result<String, EscapeError> {
+        let mut ret = String::new();
+        for (n, i) in self.parts.iter().enumerate() {
+            match i {
+                EvalPart::Literal(x) => {
+                    for c in x.chars() {
+                        match c {
+                            '$' => ret += "$$",
+                            // Spaces after `=` are skipped, so leading ones need escaping.
+                            ' ' if path || ret.is_empty() => ret += "$ ",
+                            ':' if path => ret += "$:",
+                            '|' if path => return Err(escape_error(x, c)),
+                            '\n' | '\r' | '\0' => return Err(escape_error(x, c)),
+                            c => ret.push(c),
+                        }
+                    }
+                }
+                EvalPart::Var(x) => {
+                    let simple = x
+                        .bytes()
+                        .all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-');
+                    let next_continues = match self.parts.get(n + 1) {
+                        Some(EvalPart::Literal(next)) => next
+                            .bytes()
+                            .next()
+                            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-'),
+                        _ => false,
+                    };
+                    if simple && !next_continues {
+                        ret += &format!("${x}");
+                    } else {
+                        ret += &format!("${{{x}}}");
+                    }
+                }
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// Quotes a path for a POSIX shell unless it only has safe characters, like
+/// ninja does for `$in` and `$out`.
+pub(crate) fn shell_escape(path: &str) -> String {
+    let safe = path
+        .bytes()
+        .all(|c| c.is_ascii_alphanumeric() || b"_+-./".contains(&c));
+    if safe {
+        return path.to_string();
+    }
+    format!("'{}'", path.replace('\'', "'\\''"))
+}
+
+fn escape_error(text: &str, c: char) -> EscapeError {
+    EscapeError {
+        text: text.to_string(),
+        c,
+    }
+}
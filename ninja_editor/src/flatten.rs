@@ -0,0 +1,132 @@
+use crate::{
+    parser::RULE_VARS,
+    writer::{blank_line, write_default, write_edge, write_pool, write_rule, write_var},
+    Data, EdgeKey, EscapeError, EvalPart, RuleKey,
+};
+use std::collections::{HashMap, HashSet};
+
+impl Data<'_> {
+    /// Writes the whole manifest as a single file, without `include` and
+    /// `subninja` statements.
+    ///
+    /// Variables of `subninja` scopes that shadow the top level ones are
+    /// bound on the edges that see them, and rules whose name is taken by
+    /// another scope get a numeric suffix, so every edge evaluates to the same
+    /// command as before.
+    pub fn flatten(&self) -> Result<String, EscapeError> {
+        let flattener = Flattener::new(self);
+        let mut out = String::new();
+
+        let mut section = flattener.write_header(&mut out)?;
+        blank_line(&mut out, &mut section);
+        for edge in self.edges.keys() {
+            flattener.write_edge(&mut out, edge)?;
+            section = true;
+        }
+
+        blank_line(&mut out, &mut section);
+        for default in &self.defaults {
+            write_default(&mut out, self, default)?;
+        }
+
+        if out.ends_with("\n\n") {
+            out.pop();
+        }
+        Ok(out)
+    }
+}
+
+/// Writes the parts of a manifest as if it had a single scope.
+pub(crate) struct Flattener<'a, 'x> {
+    data: &'a Data<'x>,
+    rule_names: HashMap<RuleKey, String>,
+}
+impl<'a, 'x> Flattener<'a, 'x> {
+    pub(crate) fn new(data: &'a Data<'x>) -> Self {
+        let root = data.root_scope();
+        let scope_of = |rule: RuleKey| {
+            data.rules[rule]
+                .file
+                .map_or(root, |file| data.files[file].scope)
+        };
+
+        // Top level rules keep their names, the others are renamed when
+        // needed.
+        let mut taken: HashSet<String> = HashSet::new();
+        let mut rule_names = HashMap::new();
+        let (top, nested): (Vec<_>, Vec<_>) = data.rules.keys().partition(|&x| scope_of(x) == root);
+        for rule in top.into_iter().chain(nested) {
+            let name = data.rules[rule].name.elem;
+            let mut renamed = name.to_string();
+            let mut n = 2;
+            while !taken.insert(renamed.clone()) {
+                renamed = format!("{}_{}", name, n);
+                n += 1;
+            }
+            rule_names.insert(rule, renamed);
+        }
+
+        Flattener { data, rule_names }
+    }
+
+    /// Writes the top level variables, the pools and the rules. Returns
+    /// whether anything was written.
+    pub(crate) fn write_header(&self, out: &mut String) -> Result<bool, EscapeError> {
+        let data = self.data;
+        let mut section = false;
+        for var in &data.scopes[data.root_scope()].vars {
+            write_var(out, &var.name.elem, &var.value.elem)?;
+            section = true;
+        }
+
+        for pool in data.pools.values().filter(|x| x.file.is_some()) {
+            blank_line(out, &mut section);
+            write_pool(out, pool);
+            section = true;
+        }
+
+        for (key, rule) in data.rules.iter().filter(|(_, x)| x.file.is_some()) {
+            blank_line(out, &mut section);
+            write_rule(out, &self.rule_names[&key], rule)?;
+            section = true;
+        }
+        Ok(section)
+    }
+
+    pub(crate) fn write_edge(&self, out: &mut String, key: EdgeKey) -> Result<(), EscapeError> {
+        let data = self.data;
+        let root = data.root_scope();
+        let edge = &data.edges[key];
+        let rule = &data.rules[edge.rule];
+
+        // Ninja looks up the variables of a rule, and the reserved ones it
+        // doesn't set, in the scope of the edge.
+        let mut extra = Vec::new();
+        if edge.scope != root {
+            let referenced = rule
+                .bindings
+                .iter()
+                .flat_map(|x| x.value.elem.parts())
+                .filter_map(|x| match x {
+                    EvalPart::Var(name) => Some(name.as_str()),
+                    EvalPart::Literal(_) => None,
+                });
+            let mut seen = HashSet::new();
+            for name in RULE_VARS.into_iter().chain(referenced) {
+                if matches!(name, "in" | "in_newline" | "out")
+                    || rule.binding(name).is_some()
+                    || edge.binding(name).is_some()
+                    || !seen.insert(name)
+                {
+                    continue;
+                }
+                let value = data.var(edge.scope, name).map(|x| x.value.elem.as_str());
+                let top = data.var(root, name).map(|x| x.value.elem.as_str());
+                if value != top {
+                    extra.push((name, value.unwrap_or_default()));
+                }
+            }
+        }
+        write_edge(out, data, edge, &self.rule_names[&edge.rule], &extra)
+    }
+}
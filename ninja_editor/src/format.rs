@@ -0,0 +1,242 @@
+use crate::{ItemKind, SyntaxItem, SyntaxKind, SyntaxToken, SyntaxTree};
+
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// Lines of `build` and `default` statements longer than this are
+    /// wrapped with `$` continuations, when possible.
+    pub width: usize,
+    /// Indentation of bindings and of comments between them.
+    pub indent: usize,
+    /// Indentation of the lines following a continuation.
+    pub continuation_indent: usize,
+}
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            width: 80,
+            indent: 2,
+            continuation_indent: 4,
+        }
+    }
+}
+
+impl SyntaxTree {
+    /// Formats the file: bindings and continuations get consistent
+    /// indentation, statements have single spaces between their parts, long
+    /// path lists are rewrapped and runs of blank lines are collapsed.
+    ///
+    /// Nothing is reordered and comments are kept; only whitespace changes.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        let mut formatter = Formatter {
+            tree: self,
+            options,
+            out: String::with_capacity(self.text().len()),
+        };
+        let mut blank = true;
+
+        for item in self.items() {
+            if item.kind == ItemKind::Blank {
+                if !blank {
+                    formatter.out.push('\n');
+                }
+                blank = true;
+                continue;
+            }
+            formatter.item(item, 0);
+            blank = false;
+        }
+
+        if blank && formatter.out.ends_with("\n\n") {
+            formatter.out.pop();
+        }
+        formatter.out
+    }
+}
+
+struct Formatter<'x> {
+    tree: &'x SyntaxTree,
+    options: &'x FormatOptions,
+    out: String,
+}
+impl Formatter<'_> {
+    fn item(&mut self, item: &SyntaxItem, indent: usize) {
+        self.out.extend(std::iter::repeat_n(' ', indent));
+
+        match item.kind {
+            ItemKind::Comment => {
+                let comment = item.first(SyntaxKind::Comment).unwrap();
+                self.out += self.tree.str(comment).trim_end();
+                self.out.push('\n');
+            }
+            ItemKind::Variable | ItemKind::Binding => self.assignment(item, indent),
+            ItemKind::Rule
+            | ItemKind::Pool
+            | ItemKind::Build
+            | ItemKind::Default
+            | ItemKind::Include
+            | ItemKind::Subninja => self.words(&item.tokens),
+            ItemKind::Blank => unreachable!(),
+        }
+
+        for i in &item.children {
+            self.item(i, self.options.indent);
+        }
+    }
+
+    /// `name = value`; continuations in the value are kept, as removing them
+    /// could change where a value splits into words.
+    fn assignment(&mut self, item: &SyntaxItem, indent: usize) {
+        let name = item.first(SyntaxKind::Ident).unwrap();
+        self.out += self.tree.str(name);
+        self.out += " =";
+
+        let mut first = true;
+        for i in &item.tokens {
+            match i.kind {
+                SyntaxKind::Value => {
+                    if first {
+                        self.out.push(' ');
+                        first = false;
+                    }
+                    self.out += self.tree.str(i);
+                }
+                SyntaxKind::Continuation => {
+                    if first {
+                        self.out.push(' ');
+                        first = false;
+                    }
+                    self.out += "$\n";
+                    self.out.extend(std::iter::repeat_n(
+                        ' ',
+                        indent + self.options.continuation_indent,
+                    ));
+                }
+                _ => {}
+            }
+        }
+        self.out.push('\n');
+    }
+
+    /// A statement made of keywords, paths and operators, wrapped at the
+    /// configured width.
+    fn words(&mut self, tokens: &[SyntaxToken]) {
+        let mut words: Vec<String> = Vec::new();
+        // Whether the next path piece continues the previous word, i.e. only
+        // a continuation separates them.
+        let mut joined = false;
+        let mut previous = None;
+
+        for i in tokens {
+            let text = self.tree.str(i);
+            match i.kind {
+                SyntaxKind::Path if joined => words.last_mut().unwrap().push_str(text),
+                SyntaxKind::Colon => words.last_mut().unwrap().push(':'),
+                SyntaxKind::Keyword
+                | SyntaxKind::Ident
+                | SyntaxKind::Path
+                | SyntaxKind::Pipe
+                | SyntaxKind::Pipe2
+                | SyntaxKind::PipeAt => words.push(text.to_string()),
+                _ => {}
+            }
+            joined = match i.kind {
+                SyntaxKind::Path => true,
+                SyntaxKind::Continuation => joined,
+                // Spaces after a continuation are eaten with it.
+                SyntaxKind::Whitespace => joined && previous == Some(SyntaxKind::Continuation),
+                _ => false,
+            };
+            previous = Some(i.kind);
+        }
+
+        let mut line_len = 0;
+        for (n, word) in words.iter().enumerate() {
+            if n != 0 {
+                if line_len + 1 + word.len() > self.options.width {
+                    self.out += " $\n";
+                    self.out
+                        .extend(std::iter::repeat_n(' ', self.options.continuation_indent));
+                    line_len = self.options.continuation_indent;
+                } else {
+                    self.out.push(' ');
+                    line_len += 1;
+                }
+            }
+            self.out += word;
+            line_len += word.len();
+        }
+        self.out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{validate::graph_differences, Ninja};
+    use fs_err as fs;
+
+    const MESSY: &str = "\
+# leading comment
+cflags   =    -O2 -g
+
+
+rule    cc
+      command = cc $cflags -c $in -o $out
+   # about the description
+ description = CC $out
+build   out/a.o :  cc   src/a.c   src/common/util.c    src/common/strings.c  src/common/tables.c
+    cflags = -O0
+build out/b.o: cc $
+          src/b.c
+# trailing comment   
+";
+
+    #[test]
+    fn format_keeps_comments_and_wraps() {
+        let tree = SyntaxTree::parse("build.ninja", MESSY).unwrap();
+        let options = FormatOptions {
+            width: 40,
+            ..FormatOptions::default()
+        };
+        let formatted = tree.format(&options);
+        assert_eq!(
+            formatted,
+            "\
+# leading comment
+cflags = -O2 -g
+
+rule cc
+  command = cc $cflags -c $in -o $out
+  # about the description
+  description = CC $out
+build out/a.o: cc src/a.c $
+    src/common/util.c $
+    src/common/strings.c $
+    src/common/tables.c
+  cflags = -O0
+build out/b.o: cc src/b.c
+# trailing comment
+"
+        );
+        for line in formatted.lines() {
+            assert!(line.len() <= options.width, "{line:?}");
+        }
+        let again = SyntaxTree::parse("build.ninja", &formatted).unwrap();
+        assert_eq!(again.format(&options), formatted);
+    }
+
+    #[test]
+    fn change_list_format_keeps_the_graph() {
+        let dir = std::env::temp_dir().join(format!("ninja_editor_format_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.ninja");
+        fs::write(&path, MESSY).unwrap();
+
+        let ninja = Ninja::load(&path);
+        let mut changes = ninja.change();
+        changes.format(&FormatOptions::default()).unwrap();
+        let formatted = changes.apply().unwrap();
+        assert!(graph_differences(&ninja, &formatted).is_empty());
+        assert_ne!(formatted.sm.sources[0].text(), ninja.sm.sources[0].text());
+    }
+}
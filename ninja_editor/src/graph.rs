@@ -0,0 +1,110 @@
+use crate::{Cycle, Data, EdgeKey, NodeKey, PathKind};
+use std::collections::HashSet;
+
+impl Data<'_> {
+    /// Every edge, each after the edges generating its inputs, including
+    /// order-only ones and the inputs added by loaded dyndep files. Fails
+    /// with the first of [`Data::cycles`] if there are any.
+    pub fn topological_order(&self) -> Result<impl Iterator<Item = EdgeKey>, Cycle> {
+        if let Some(cycle) = self.cycles().into_iter().next() {
+            return Err(cycle);
+        }
+        let edges = post_order(self.edges.keys(), |edge| self.input_edges(edge, true));
+        Ok(edges.into_iter())
+    }
+
+    /// The edges ninja runs to build `targets`, each after the edges
+    /// generating its inputs. Without `order_only`, the edges only needed
+    /// for order-only inputs are left out.
+    pub fn edges_for_targets(
+        &self,
+        targets: &[NodeKey],
+        order_only: bool,
+    ) -> impl Iterator<Item = EdgeKey> {
+        let starts = targets.iter().filter_map(|&x| self.nodes[x].in_edge);
+        post_order(starts, |edge| self.input_edges(edge, order_only)).into_iter()
+    }
+
+    /// The edges depending on `nodes`, directly or through other edges,
+    /// each after the edges generating its inputs. Without `order_only`,
+    /// these are the edges ninja reruns when one of `nodes` changes.
+    pub fn dependent_edges(
+        &self,
+        nodes: &[NodeKey],
+        order_only: bool,
+    ) -> impl Iterator<Item = EdgeKey> {
+        let starts = nodes
+            .iter()
+            .flat_map(|&x| self.consumers(x, order_only))
+            .collect::<Vec<_>>();
+        let mut edges = post_order(starts, |edge| {
+            self.edges[edge]
+                .outputs()
+                .flat_map(|x| self.consumers(x, order_only))
+                .collect()
+        });
+        edges.reverse();
+        edges.into_iter()
+    }
+
+    /// The nodes [`Data::dependent_edges`] generate.
+    pub fn dependent_nodes(
+        &self,
+        nodes: &[NodeKey],
+        order_only: bool,
+    ) -> impl Iterator<Item = NodeKey> + '_ {
+        self.dependent_edges(nodes, order_only)
+            .flat_map(|x| self.edges[x].outputs())
+    }
+
+    /// The edges generating the inputs of `edge`.
+    fn input_edges(&self, edge: EdgeKey, order_only: bool) -> Vec<EdgeKey> {
+        self.edges[edge]
+            .inputs()
+            .filter(|&(kind, _)| order_only || kind != PathKind::OrderOnlyIn)
+            .filter_map(|(_, x)| self.nodes[x].in_edge)
+            .collect()
+    }
+
+    /// The edges having `node` as an input.
+    fn consumers(&self, node: NodeKey, order_only: bool) -> impl Iterator<Item = EdgeKey> + '_ {
+        self.nodes[node]
+            .out_edges
+            .iter()
+            .copied()
+            .filter(move |&edge| {
+                self.edges[edge]
+                    .inputs()
+                    .any(|(kind, x)| x == node && (order_only || kind != PathKind::OrderOnlyIn))
+            })
+    }
+}
+
+/// The edges reachable from `starts` through `next`, each after those it
+/// reaches; edges on cycles are visited once.
+fn post_order<I, F>(starts: I, mut next: F) -> Vec<EdgeKey>
+where
+    I: IntoIterator<Item = EdgeKey>,
+    F: FnMut(EdgeKey) -> Vec<EdgeKey>,
+{
+    let mut ret = Vec::new();
+    let mut visited = HashSet::new();
+    for start in starts {
+        if !visited.insert(start) {
+            continue;
+        }
+        let mut stack = vec![(start, next(start), 0)];
+        while let Some((edge, children, n)) = stack.last_mut() {
+            let Some(&child) = children.get(*n) else {
+                ret.push(*edge);
+                stack.pop();
+                continue;
+            };
+            *n += 1;
+            if visited.insert(child) {
+                stack.push((child, next(child), 0));
+            }
+        }
+    }
+    ret
+}
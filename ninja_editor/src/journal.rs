@@ -0,0 +1,476 @@
+use filetime::{set_file_mtime, FileTime};
+use fs_err as fs;
+use std::{
+    fmt, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+const HEADER: &str = "ninja_editor journal v1";
+
+/// A record of the files written by a commit, with enough of their original
+/// contents to restore them exactly.
+///
+/// The format is line based; texts are prefixed by their length in bytes:
+///
+/// ```text
+/// ninja_editor journal v1
+/// file <path length>
+/// <path>
+/// mtime <seconds> <nanoseconds>
+/// hash <original hash> <new hash>
+/// change <start> <length> <original length>
+/// <original text>
+/// end
+/// ```
+///
+/// `start` and `length` describe where the replacement text is in the new
+/// file. Texts are bytes, as the binary `.ninja_deps` is journaled too.
+#[derive(Debug, Default)]
+pub struct Journal {
+    files: Vec<JournalFile>,
+}
+
+#[derive(Debug)]
+struct JournalFile {
+    path: PathBuf,
+    mtime: FileTime,
+    original_hash: u64,
+    new_hash: u64,
+    changes: Vec<JournalChange>,
+}
+
+#[derive(Debug)]
+struct JournalChange {
+    new_range: Range<usize>,
+    original_text: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    Malformed(String),
+    /// The file was changed since the commit that wrote the journal.
+    Modified(PathBuf),
+}
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::Io(e) => write!(f, "{e}"),
+            JournalError::Malformed(e) => write!(f, "malformed journal: {e}"),
+            JournalError::Modified(path) => {
+                write!(f, "`{}` was modified after the commit", path.display())
+            }
+        }
+    }
+}
+impl std::error::Error for JournalError {}
+impl From<io::Error> for JournalError {
+    fn from(e: io::Error) -> Self {
+        JournalError::Io(e)
+    }
+}
+
+impl Journal {
+    /// Records a file about to be overwritten. `changes` are the ranges of the
+    /// new text that replaced the given original texts, in order.
+    pub(crate) fn add_file(
+        &mut self,
+        path: &Path,
+        original_text: &[u8],
+        new_text: &[u8],
+        changes: Vec<(Range<usize>, Vec<u8>)>,
+    ) -> io::Result<()> {
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(path)?);
+        let changes = changes
+            .into_iter()
+            .map(|(new_range, original_text)| JournalChange {
+                new_range,
+                original_text,
+            })
+            .collect();
+
+        self.files.push(JournalFile {
+            path: std::path::absolute(path)?,
+            mtime,
+            original_hash: hash(original_text),
+            new_hash: hash(new_text),
+            changes,
+        });
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = format!("{HEADER}\n").into_bytes();
+        for file in &self.files {
+            let path = file.path.to_string_lossy();
+            out.extend(format!("file {}\n{}\n", path.len(), path).bytes());
+            out.extend(
+                format!(
+                    "mtime {} {}\n",
+                    file.mtime.unix_seconds(),
+                    file.mtime.nanoseconds()
+                )
+                .bytes(),
+            );
+            out.extend(
+                format!("hash {:016x} {:016x}\n", file.original_hash, file.new_hash).bytes(),
+            );
+            for change in &file.changes {
+                out.extend(
+                    format!(
+                        "change {} {} {}\n",
+                        change.new_range.start,
+                        change.new_range.len(),
+                        change.original_text.len(),
+                    )
+                    .bytes(),
+                );
+                out.extend(&change.original_text);
+                out.push(b'\n');
+            }
+            out.extend(b"end\n");
+        }
+        fs::write(path, out)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Journal, JournalError> {
+        let text = fs::read(path)?;
+        let mut reader = Reader { text: &text };
+
+        if reader.line()? != HEADER {
+            return Err(JournalError::Malformed("unknown header".into()));
+        }
+
+        let mut journal = Journal::default();
+        while !reader.text.is_empty() {
+            let path_len = number(reader.keyword_line("file")?)?;
+            let path = std::str::from_utf8(reader.blob(path_len)?)
+                .map_err(|_| JournalError::Malformed("path isn't UTF-8".into()))?;
+            let path = PathBuf::from(path);
+
+            let mut mtime = reader.keyword_line("mtime")?.split(' ');
+            let seconds = number(mtime.next().unwrap_or_default())?;
+            let nanos = number(mtime.next().unwrap_or_default())?;
+            let mtime = FileTime::from_unix_time(seconds, nanos);
+
+            let mut hashes = reader.keyword_line("hash")?.split(' ');
+            let original_hash = parse_hash(hashes.next().unwrap_or_default())?;
+            let new_hash = parse_hash(hashes.next().unwrap_or_default())?;
+
+            let mut changes = Vec::new();
+            loop {
+                let line = reader.line()?;
+                if line == "end" {
+                    break;
+                }
+                let Some(line) = line.strip_prefix("change ") else {
+                    return Err(JournalError::Malformed(format!("unexpected `{line}`")));
+                };
+                let mut numbers = line.split(' ');
+                let start = number(numbers.next().unwrap_or_default())?;
+                let len: usize = number(numbers.next().unwrap_or_default())?;
+                let original_len = number(numbers.next().unwrap_or_default())?;
+                changes.push(JournalChange {
+                    new_range: start..start + len,
+                    original_text: reader.blob(original_len)?.to_vec(),
+                });
+            }
+
+            journal.files.push(JournalFile {
+                path,
+                mtime,
+                original_hash,
+                new_hash,
+                changes,
+            });
+        }
+
+        Ok(journal)
+    }
+
+    /// Restores every file to its state before the commit, including its
+    /// modification time.
+    ///
+    /// Nothing is written unless all files still have the contents the commit
+    /// left them with. Files that already have their original contents are
+    /// skipped.
+    pub fn revert(&self) -> Result<(), JournalError> {
+        let mut writes = Vec::new();
+        for file in &self.files {
+            let text = fs::read(&file.path)?;
+            let text_hash = hash(&text);
+            if text_hash == file.original_hash {
+                continue;
+            }
+            if text_hash != file.new_hash {
+                return Err(JournalError::Modified(file.path.clone()));
+            }
+
+            let original = file.restore(&text)?;
+            if hash(&original) != file.original_hash {
+                return Err(JournalError::Malformed(format!(
+                    "restoring `{}` doesn't give back the original text",
+                    file.path.display()
+                )));
+            }
+            writes.push((file, original));
+        }
+
+        for (file, text) in writes {
+            fs::write(&file.path, text)?;
+            set_file_mtime(&file.path, file.mtime)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl JournalFile {
+    fn restore(&self, text: &[u8]) -> Result<Vec<u8>, JournalError> {
+        let mut original = Vec::with_capacity(text.len());
+        let mut offset = 0;
+
+        for change in &self.changes {
+            let range = &change.new_range;
+            if range.start < offset || range.end > text.len() {
+                return Err(JournalError::Malformed(format!(
+                    "bad change range {range:?} for `{}`",
+                    self.path.display()
+                )));
+            }
+            original.extend(&text[offset..range.start]);
+            original.extend(&change.original_text);
+            offset = range.end;
+        }
+
+        original.extend(&text[offset..]);
+        Ok(original)
+    }
+}
+
+struct Reader<'x> {
+    text: &'x [u8],
+}
+impl<'x> Reader<'x> {
+    fn line(&mut self) -> Result<&'x str, JournalError> {
+        let Some(end) = self.text.iter().position(|&x| x == b'\n') else {
+            return Err(JournalError::Malformed("unexpected end of file".into()));
+        };
+        let line = std::str::from_utf8(&self.text[..end])
+            .map_err(|_| JournalError::Malformed("line isn't UTF-8".into()))?;
+        self.text = &self.text[end + 1..];
+        Ok(line)
+    }
+    fn keyword_line(&mut self, keyword: &str) -> Result<&'x str, JournalError> {
+        let line = self.line()?;
+        match line.strip_prefix(keyword).and_then(|x| x.strip_prefix(' ')) {
+            Some(rest) => Ok(rest),
+            None => Err(JournalError::Malformed(format!(
+                "expected `{keyword}`, got `{line}`"
+            ))),
+        }
+    }
+    /// A text of `len` bytes followed by a newline.
+    fn blob(&mut self, len: usize) -> Result<&'x [u8], JournalError> {
+        match self.text.get(..len) {
+            Some(blob) if self.text[len..].starts_with(b"\n") => {
+                self.text = &self.text[len + 1..];
+                Ok(blob)
+            }
+            _ => Err(JournalError::Malformed("truncated text".into())),
+        }
+    }
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> Result<T, JournalError> {
+    s.parse()
+        .map_err(|_| JournalError::Malformed(format!("expected a number, got `{s}`")))
+}
+
+fn parse_hash(s: &str) -> Result<u64, JournalError> {
+    u64::from_str_radix(s, 16)
+        .map_err(|_| JournalError::Malformed(format!("expected a hash, got `{s}`")))
+}
+
+/// 64-bit FNV-1a; stable across platforms and compiler versions.
+fn hash(text: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in text {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DepsEntry, DepsLog, Ninja};
+
+    /// An empty directory of its own for each test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "ninja_editor_journal_{}_{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn commit_then_revert() {
+        let dir = test_dir("commit_then_revert");
+        let manifest = dir.join("build.ninja");
+        let text = "rule cc\n  command = cc $in\nbuild a.o: cc a.c\nbuild b.o: cc b.c\n";
+        fs::write(&manifest, text).unwrap();
+        let mtime = FileTime::from_unix_time(1_500_000_000, 123);
+        set_file_mtime(&manifest, mtime).unwrap();
+
+        let ninja = Ninja::load(&manifest);
+        let mut changes = ninja.change();
+        let (rule, _) = ninja
+            .data()
+            .rules
+            .iter()
+            .find(|x| x.1.name.elem == "cc")
+            .unwrap();
+        changes.rename_rule(rule, "compile");
+        changes.set_journal(dir.join("journal"));
+        changes.commit().unwrap();
+        let renamed = fs::read_to_string(&manifest).unwrap();
+        assert_eq!(renamed.matches("compile").count(), 3);
+
+        Journal::load(dir.join("journal"))
+            .unwrap()
+            .revert()
+            .unwrap();
+        assert_eq!(fs::read_to_string(&manifest).unwrap(), text);
+        let restored = FileTime::from_last_modification_time(&fs::metadata(&manifest).unwrap());
+        assert_eq!(restored, mtime);
+    }
+
+    /// The deps log isn't text: its path ids have NULs and bytes that aren't
+    /// UTF-8, which must come back unchanged.
+    #[test]
+    fn revert_the_deps_log() {
+        let dir = test_dir("revert_the_deps_log");
+        let manifest = dir.join("build.ninja");
+        let text = "rule cc\n  command = cc $in\n  deps = gcc\nbuild out/a.o: cc a.c\n";
+        fs::write(&manifest, text).unwrap();
+        let deps_path = dir.join(".ninja_deps");
+        let log = DepsLog {
+            entries: vec![DepsEntry {
+                output: "out/a.o".to_string(),
+                mtime: 1,
+                inputs: vec!["a.c".to_string(), "out/gen.h".to_string()],
+            }],
+        };
+        let deps = log.to_bytes().unwrap();
+        assert!(std::str::from_utf8(&deps).is_err());
+        fs::write(&deps_path, &deps).unwrap();
+
+        let ninja = Ninja::load(&manifest);
+        let mut changes = ninja.change();
+        changes.rename_prefix("out/", "obj/").unwrap();
+        changes.set_deps_log(&deps_path);
+        changes.set_journal(dir.join("journal"));
+        changes.commit().unwrap();
+        let renamed = DepsLog::load(&deps_path).unwrap();
+        assert_eq!(renamed.entries[0].output, "obj/a.o");
+        assert_eq!(renamed.entries[0].inputs, ["a.c", "obj/gen.h"]);
+
+        Journal::load(dir.join("journal"))
+            .unwrap()
+            .revert()
+            .unwrap();
+        assert_eq!(fs::read(&deps_path).unwrap(), deps);
+        assert_eq!(fs::read_to_string(&manifest).unwrap(), text);
+    }
+
+    /// Original texts are length prefixed, so line breaks and lines looking
+    /// like the journal's own keywords don't end them.
+    #[test]
+    fn texts_looking_like_the_format() {
+        let dir = test_dir("texts_looking_like_the_format");
+        let path = dir.join("file");
+        let original = "a\nend\nchange 0 0 0\nfile 3\nb\nc";
+        let new = "a\nX\nb\nY";
+        fs::write(&path, new).unwrap();
+        let changes = vec![
+            (2..3, b"end\nchange 0 0 0\nfile 3".to_vec()),
+            // A deletion, then an insertion at the same place.
+            (6..6, b"c".to_vec()),
+            (6..7, Vec::new()),
+        ];
+        let mut journal = Journal::default();
+        journal
+            .add_file(&path, original.as_bytes(), new.as_bytes(), changes)
+            .unwrap();
+        journal.save(dir.join("journal")).unwrap();
+
+        let journal = Journal::load(dir.join("journal")).unwrap();
+        assert_eq!(journal.files[0].changes.len(), 3);
+        journal.revert().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        // Already reverted: nothing to do.
+        journal.revert().unwrap();
+    }
+
+    #[test]
+    fn revert_writes_nothing_if_a_file_was_modified() {
+        let dir = test_dir("revert_writes_nothing");
+        let (first, second) = (dir.join("first"), dir.join("second"));
+        let mut journal = Journal::default();
+        for path in [&first, &second] {
+            fs::write(path, "new").unwrap();
+            let changes = vec![(0..3, b"old".to_vec())];
+            journal.add_file(path, b"old", b"new", changes).unwrap();
+        }
+        fs::write(&second, "edited after the commit").unwrap();
+
+        let error = journal.revert().unwrap_err();
+        assert!(matches!(error, JournalError::Modified(path) if path == second));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "new");
+    }
+
+    #[test]
+    fn malformed() {
+        let dir = test_dir("malformed");
+        let path = dir.join("file");
+        fs::write(&path, "new").unwrap();
+        let load = |text: String| {
+            fs::write(dir.join("journal"), text).unwrap();
+            Journal::load(dir.join("journal"))
+        };
+        let journal = |change: &str| {
+            let path = path.to_string_lossy();
+            format!(
+                "{HEADER}\nfile {}\n{path}\nmtime 0 0\nhash {:016x} {:016x}\n{change}end\n",
+                path.len(),
+                hash(b"old"),
+                hash(b"new"),
+            )
+        };
+
+        // The change is past the end of the file.
+        let error = load(journal("change 2 5 3\nold\n")).unwrap().revert();
+        assert!(matches!(error, Err(JournalError::Malformed(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+
+        // The original text is shorter than its length says.
+        let error = load(journal("change 0 3 9\nold\n")).unwrap_err();
+        assert_eq!(error.to_string(), "malformed journal: truncated text");
+
+        let error = load(journal("change 0 3 3\nold\n").replace("end\n", "")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "malformed journal: unexpected end of file"
+        );
+
+        let error = load("ninja_editor journal v2\n".to_string()).unwrap_err();
+        assert_eq!(error.to_string(), "malformed journal: unknown header");
+    }
+}
@@ -0,0 +1,553 @@
+//! The build graph as JSON, for tools that don't parse ninja themselves.
+//!
+//! The document is a [`Manifest`]; every type of this module maps to a JSON
+//! object with the same field names. Objects refer to each other by their
+//! index in the arrays of the manifest, paths are strings as ninja sees them
+//! after evaluation.
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "files": [{"path": "build.ninja", "kind": "root", "parent": null, "scope": 0}],
+//!   "scopes": [{"parent": null, "variables": [{"name": "cflags", "value": "-O2", "location": {..}}]}],
+//!   "pools": [{"name": "console", "depth": 1, "location": null}],
+//!   "rules": [{"name": "cc", "scope": 0, "bindings": [{"name": "command", "value": "cc $in -o $out"}], "location": {..}}],
+//!   "edges": [{"rule": 1, "file": 0, "outputs": ["a.o"], "implicit_outputs": [], "inputs": ["a.c"],
+//!              "implicit_inputs": [], "order_only_inputs": [], "validations": [], "bindings": [], "location": {..}}],
+//!   "defaults": ["a.o"]
+//! }
+//! ```
+//!
+//! [`Manifest::to_ninja`] goes the other way. Locations are optional there
+//! and only used for error messages, as are the builtin `phony` rule and
+//! `console` pool.
+//!
+//! [`VERSION`] is increased whenever a field is removed or changes meaning;
+//! fields may be added without a new version.
+
+use crate::{
+    lexer::LOC_INVALID, parser::RULE_VARS, Data, Diagnostic, EdgeKey, EscapeError, EvalString,
+    FileKey, FileKind, Location, Ninja, PathKind, RuleKey, ScopeKey, ValidationError, L,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The version of the schema written by [`Ninja::to_json`].
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// See [`VERSION`].
+    pub version: u32,
+    /// The root file first, then the `include` and `subninja` files in the
+    /// order they were loaded.
+    pub files: Vec<File>,
+    /// The scope of the root file first, then one per `subninja` file.
+    pub scopes: Vec<Scope>,
+    /// Including the builtin `console` pool.
+    pub pools: Vec<Pool>,
+    /// Including the builtin `phony` rule.
+    pub rules: Vec<Rule>,
+    pub edges: Vec<Edge>,
+    /// Targets of the `default` statements, in order.
+    pub defaults: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct File {
+    pub path: String,
+    /// `"root"`, `"include"` or `"subninja"`.
+    pub kind: String,
+    /// Index of the file with the `include` or `subninja` statement.
+    pub parent: Option<usize>,
+    /// Index of the scope the file's variables and rules are in.
+    pub scope: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scope {
+    pub parent: Option<usize>,
+    /// Evaluated top level variables with their final values.
+    pub variables: Vec<Variable>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variable {
+    pub name: String,
+    pub value: String,
+    /// Of the last assignment; `null` for variables that weren't parsed.
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pool {
+    pub name: String,
+    pub depth: u32,
+    /// `null` for builtin pools.
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    /// Index of the scope the rule is defined in.
+    pub scope: usize,
+    /// Values as written in the manifest, with `$` escapes and variable
+    /// references.
+    pub bindings: Vec<Binding>,
+    /// `null` for builtin rules.
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Binding {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Edge {
+    /// Index of the rule.
+    pub rule: usize,
+    /// Index of the file with the `build` statement.
+    pub file: usize,
+    pub outputs: Vec<String>,
+    #[serde(default)]
+    pub implicit_outputs: Vec<String>,
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub implicit_inputs: Vec<String>,
+    #[serde(default)]
+    pub order_only_inputs: Vec<String>,
+    #[serde(default)]
+    pub validations: Vec<String>,
+    /// Evaluated values of the bindings of the `build` statement.
+    #[serde(default)]
+    pub bindings: Vec<Variable>,
+    /// Of the `build` keyword.
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+/// 1-based position in a manifest file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Ninja {
+    /// The graph in the schema of the [`json`](crate::json) module. Fails if
+    /// a rule binding has a character a manifest can't hold.
+    pub fn to_manifest(&self) -> Result<Manifest, EscapeError> {
+        let data = &self.data;
+        let location = |loc: Location| {
+            if loc == LOC_INVALID {
+                return None;
+            }
+            let source = self.sm.get(loc.source_id);
+            let (line, column) = source.line_col(loc.start);
+            Some(SourceLocation {
+                file: source.path.to_string_lossy().into_owned(),
+                line,
+                column,
+            })
+        };
+        let variable = |x: &crate::Variable| Variable {
+            name: x.name.elem.clone(),
+            value: x.value.elem.clone(),
+            location: location(x.name.loc),
+        };
+        let paths = |edge: EdgeKey, kind| {
+            data.edges[edge]
+                .paths(kind)
+                .iter()
+                .map(|x| data.nodes[x.elem].path.clone())
+                .collect()
+        };
+
+        let scopes: HashMap<ScopeKey, usize> = data
+            .scopes
+            .keys()
+            .enumerate()
+            .map(|(n, x)| (x, n))
+            .collect();
+        let files: HashMap<_, usize> = data.files.keys().enumerate().map(|(n, x)| (x, n)).collect();
+        let rules: HashMap<RuleKey, usize> =
+            data.rules.keys().enumerate().map(|(n, x)| (x, n)).collect();
+
+        Ok(Manifest {
+            version: VERSION,
+            files: data
+                .files
+                .values()
+                .map(|x| File {
+                    path: x.path.elem.clone(),
+                    kind: match x.kind {
+                        FileKind::Root => "root",
+                        FileKind::Include => "include",
+                        FileKind::Subninja => "subninja",
+                    }
+                    .to_string(),
+                    parent: x.parent.map(|x| files[&x]),
+                    scope: scopes[&x.scope],
+                })
+                .collect(),
+            scopes: data
+                .scopes
+                .values()
+                .map(|x| Scope {
+                    parent: x.parent.map(|x| scopes[&x]),
+                    variables: x.vars.iter().map(variable).collect(),
+                })
+                .collect(),
+            pools: data
+                .pools
+                .values()
+                .map(|x| Pool {
+                    name: x.name.elem.clone(),
+                    depth: x.depth.elem,
+                    location: location(x.name.loc),
+                })
+                .collect(),
+            rules: data
+                .rules
+                .values()
+                .map(|x| {
+                    Ok(Rule {
+                        name: x.name.elem.to_string(),
+                        scope: x.file.map_or(0, |file| scopes[&data.files[file].scope]),
+                        bindings: x
+                            .bindings
+                            .iter()
+                            .map(|x| {
+                                Ok(Binding {
+                                    name: x.name.elem.clone(),
+                                    value: x.value.elem.to_value()?,
+                                })
+                            })
+                            .collect::<Result<_, EscapeError>>()?,
+                        location: location(x.name.loc),
+                    })
+                })
+                .collect::<Result<_, EscapeError>>()?,
+            edges: data
+                .edges
+                .iter()
+                .map(|(key, x)| Edge {
+                    rule: rules[&x.rule],
+                    file: files[&x.file],
+                    outputs: paths(key, PathKind::Out),
+                    implicit_outputs: paths(key, PathKind::ImplicitOut),
+                    inputs: paths(key, PathKind::In),
+                    implicit_inputs: paths(key, PathKind::ImplicitIn),
+                    order_only_inputs: paths(key, PathKind::OrderOnlyIn),
+                    validations: paths(key, PathKind::Validation),
+                    bindings: x.bindings.iter().map(variable).collect(),
+                    location: location(x.loc),
+                })
+                .collect(),
+            defaults: data
+                .defaults
+                .iter()
+                .flat_map(|x| &x.targets)
+                .map(|x| data.nodes[x.elem].path.clone())
+                .collect(),
+        })
+    }
+
+    /// [`Ninja::to_manifest`] as pretty printed JSON.
+    pub fn to_json(&self) -> Result<String, EscapeError> {
+        Ok(serde_json::to_string_pretty(&self.to_manifest()?).unwrap())
+    }
+}
+
+impl Manifest {
+    pub fn from_json(text: &str) -> Result<Manifest, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+
+    /// Builds the model the manifest describes. Fails with every problem
+    /// found: references to missing files, scopes or rules, invalid rules or
+    /// names, outputs produced by several edges, unknown default targets and
+    /// dependency cycles.
+    pub fn to_data(&self) -> Result<Data<'_>, ValidationError> {
+        let root_path = self.files.first().map_or("build.ninja", |x| &x.path);
+        let error = |location: &Option<SourceLocation>, message: String| match location {
+            Some(x) => Diagnostic {
+                path: x.file.clone().into(),
+                line: x.line,
+                column: x.column,
+                message,
+            },
+            None => Diagnostic::for_file(root_path, message),
+        };
+        let failed = |diagnostics| Err(ValidationError { diagnostics });
+
+        if self.version != VERSION {
+            let message = format!("unsupported version {}, expected {}", self.version, VERSION);
+            return failed(vec![error(&None, message)]);
+        }
+
+        // Files and scopes are referenced by everything else, so stop early
+        // if they are broken.
+        let mut data = Data::new();
+        let mut errors = Vec::new();
+        let mut files: Vec<FileKey> = Vec::new();
+        let mut scope_files: HashMap<usize, FileKey> = HashMap::new();
+        for (n, file) in self.files.iter().enumerate() {
+            let kind = match file.kind.as_str() {
+                "root" => FileKind::Root,
+                "include" => FileKind::Include,
+                "subninja" => FileKind::Subninja,
+                _ => {
+                    errors.push(error(
+                        &None,
+                        format!("file {}: unknown kind `{}`", n, file.kind),
+                    ));
+                    break;
+                }
+            };
+            let key = match (n, kind, file.parent) {
+                (0, FileKind::Root, None) => {
+                    let root = data.root_file();
+                    data.files[root].path = L::synthetic(file.path.clone());
+                    root
+                }
+                (1.., FileKind::Include | FileKind::Subninja, Some(parent)) if parent < n => {
+                    data.add_file(files[parent], &file.path, kind)
+                }
+                _ => {
+                    let message = format!(
+                        "file {}: only the first file is the root, the others need an earlier parent",
+                        n
+                    );
+                    errors.push(error(&None, message));
+                    break;
+                }
+            };
+            files.push(key);
+
+            let scope = data.files[key].scope;
+            match scope_files.get(&file.scope) {
+                Some(&x) if data.files[x].scope != scope => {
+                    let message = format!(
+                        "file {}: not in the same scope as file of scope {}",
+                        n, file.scope
+                    );
+                    errors.push(error(&None, message));
+                }
+                Some(_) => {}
+                None => {
+                    scope_files.insert(file.scope, key);
+                }
+            }
+        }
+        if files.is_empty() {
+            errors.push(error(&None, "expected a root file".to_string()));
+        }
+        if !errors.is_empty() {
+            return failed(errors);
+        }
+
+        for (n, scope) in self.scopes.iter().enumerate() {
+            let Some(&file) = scope_files.get(&n) else {
+                if !scope.variables.is_empty() {
+                    errors.push(error(&None, format!("scope {}: no file is in it", n)));
+                }
+                continue;
+            };
+            for var in &scope.variables {
+                if !is_ident(&var.name) {
+                    errors.push(error(
+                        &var.location,
+                        format!("invalid variable name `{}`", var.name),
+                    ));
+                    continue;
+                }
+                data.set_var(file, &var.name, &var.value);
+            }
+        }
+
+        for pool in &self.pools {
+            let builtin = pool.location.is_none() && pool.name == "console" && pool.depth == 1;
+            if builtin {
+                continue;
+            }
+            if !is_ident(&pool.name) {
+                errors.push(error(
+                    &pool.location,
+                    format!("invalid pool name `{}`", pool.name),
+                ));
+            } else if data.add_pool(files[0], &pool.name, pool.depth).is_none() {
+                errors.push(error(
+                    &pool.location,
+                    format!("duplicate pool `{}`", pool.name),
+                ));
+            }
+        }
+
+        let mut rules: Vec<Option<RuleKey>> = Vec::new();
+        for rule in &self.rules {
+            let builtin =
+                rule.location.is_none() && rule.name == "phony" && rule.bindings.is_empty();
+            if builtin {
+                rules.push(data.rule(data.root_scope(), "phony"));
+                continue;
+            }
+            let key = self.to_rule(&mut data, rule, &scope_files);
+            rules.push(
+                key.map_err(|message| errors.push(error(&rule.location, message)))
+                    .ok(),
+            );
+        }
+
+        for (n, edge) in self.edges.iter().enumerate() {
+            let Some(&file) = files.get(edge.file) else {
+                errors.push(error(
+                    &edge.location,
+                    format!("edge {}: unknown file {}", n, edge.file),
+                ));
+                continue;
+            };
+            let scope = data.files[file].scope;
+            let rule = match rules.get(edge.rule) {
+                Some(Some(rule)) => *rule,
+                // Already reported.
+                Some(None) => continue,
+                None => {
+                    let message = format!("edge {}: unknown rule {}", n, edge.rule);
+                    errors.push(error(&edge.location, message));
+                    continue;
+                }
+            };
+            let name = &self.rules[edge.rule].name;
+            if data.rule(scope, name) != Some(rule) {
+                errors.push(error(&edge.location, format!("unknown rule `{}`", name)));
+                continue;
+            }
+            if edge.outputs.is_empty() {
+                errors.push(error(&edge.location, "expected path".to_string()));
+                continue;
+            }
+
+            let key = data.add_edge(file, rule);
+            let paths = [
+                (PathKind::Out, &edge.outputs),
+                (PathKind::ImplicitOut, &edge.implicit_outputs),
+                (PathKind::In, &edge.inputs),
+                (PathKind::ImplicitIn, &edge.implicit_inputs),
+                (PathKind::OrderOnlyIn, &edge.order_only_inputs),
+                (PathKind::Validation, &edge.validations),
+            ];
+            for (kind, paths) in paths {
+                for path in paths {
+                    if path.is_empty() {
+                        errors.push(error(&edge.location, "empty path".to_string()));
+                        continue;
+                    }
+                    let produced = data.node(path).and_then(|x| data.nodes[x].in_edge);
+                    if kind.is_output() && produced.is_some() {
+                        errors.push(error(
+                            &edge.location,
+                            format!("multiple rules generate {}", path),
+                        ));
+                        continue;
+                    }
+                    data.add_edge_path(key, kind, path);
+                }
+            }
+            for var in &edge.bindings {
+                if !is_ident(&var.name) {
+                    errors.push(error(
+                        &var.location,
+                        format!("invalid variable name `{}`", var.name),
+                    ));
+                    continue;
+                }
+                data.add_edge_binding(key, &var.name, &var.value);
+            }
+        }
+
+        for target in &self.defaults {
+            match data.node(target) {
+                Some(_) => data.add_default(files[0], &[target]),
+                None => errors.push(error(&None, format!("unknown target `{}`", target))),
+            }
+        }
+
+        if let Some(cycle) = data.cycles().first() {
+            let message = format!("dependency cycle: {}", cycle.chain(&data));
+            errors.push(error(&None, message));
+        }
+
+        if !errors.is_empty() {
+            return failed(errors);
+        }
+        Ok(data)
+    }
+
+    fn to_rule<'x>(
+        &'x self,
+        data: &mut Data<'x>,
+        rule: &'x Rule,
+        scope_files: &HashMap<usize, FileKey>,
+    ) -> Result<RuleKey, String> {
+        if !is_ident(&rule.name) {
+            return Err(format!("invalid rule name `{}`", rule.name));
+        }
+        let Some(&file) = scope_files.get(&rule.scope) else {
+            return Err(format!(
+                "rule `{}`: no file is in scope {}",
+                rule.name, rule.scope
+            ));
+        };
+
+        let mut bindings = Vec::new();
+        for i in &rule.bindings {
+            if !RULE_VARS.contains(&i.name.as_str()) {
+                return Err(format!("unexpected variable `{}`", i.name));
+            }
+            let value = EvalString::parse_value(&i.value)
+                .map_err(|e| format!("invalid value of `{}`: {}", i.name, e.message))?;
+            bindings.push((i.name.clone(), value));
+        }
+        let has = |name: &str| bindings.iter().any(|(x, _)| x == name);
+        if !has("command") {
+            return Err(format!("rule `{}`: expected `command =` line", rule.name));
+        }
+        if has("rspfile") != has("rspfile_content") {
+            return Err(format!(
+                "rule `{}`: rspfile and rspfile_content need to be both specified",
+                rule.name
+            ));
+        }
+
+        data.add_rule(file, &rule.name, bindings)
+            .ok_or_else(|| format!("duplicate rule `{}`", rule.name))
+    }
+
+    /// Ninja text of every file, root first; see [`Manifest::to_data`] and
+    /// [`Data::write`].
+    pub fn to_ninja(&self) -> Result<Vec<(String, String)>, ValidationError> {
+        let data = self.to_data()?;
+        data.write().map_err(|e| {
+            let path = &data.files[data.root_file()].path.elem;
+            ValidationError {
+                diagnostics: vec![Diagnostic::for_file(path, e.to_string())],
+            }
+        })
+    }
+}
+
+fn is_ident(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || b"_.-".contains(&c))
+}
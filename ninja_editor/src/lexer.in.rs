@@ -1,6 +1,6 @@
-use crate::{L, SourceId};
+use crate::{diagnostic::ParseError, eval::EvalString, SourceId, L};
 
-#[derive(Default, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Default, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Location {
     pub(crate) start: usize,
     pub(crate) stop: usize,
@@ -71,7 +71,7 @@ pub struct Lexer<'x> {
 }
 
 impl<'x> Lexer<'x> {
-    pub fn new(text: &str, source_id: SourceId) -> Lexer {
+    pub fn new(text: &str, source_id: SourceId) -> Lexer<'_> {
         Lexer {
             text: text.as_bytes(),
             text_str: text,
@@ -79,7 +79,7 @@ impl<'x> Lexer<'x> {
             source_id,
         }
     }
-    pub fn next_impl(&mut self) -> Token {
+    pub fn next_impl(&mut self) -> Result<Token, ParseError> {
         let start_offset = self.offset;
         let mut offset = self.offset;
         let token;
@@ -115,7 +115,7 @@ impl<'x> Lexer<'x> {
         "subninja" { token = K::Subninja; break; }
         varname    { token = K::Ident;    break; }
         nul        { token = K::Eof;      break; }
-        [^]        { panic!("error");            }
+        [^]        { return Err(self.error("unexpected character", start_offset)); }
         */
 
         self.offset = offset;
@@ -126,36 +126,51 @@ impl<'x> Lexer<'x> {
             source_id: self.source_id,
         };
 
-        if token != K::Newline && token != K::Eof {
+        // Comments end with a line break, so what follows is an indent.
+        if token != K::Newline && token != K::Eof && token != K::Comment {
             self.eat_whitespace();
         }
 
-        Token { kind: token, loc }
+        Ok(Token { kind: token, loc })
     }
-    pub fn next(&mut self) -> Token {
+    pub fn next(&mut self) -> Result<Token, ParseError> {
         loop {
-            let next = self.next_impl();
+            let next = self.next_impl()?;
             if next.kind == K::Comment {
                 continue;
             }
-            return next;
+            return Ok(next);
         }
     }
-    pub fn maybe_peek(&mut self, kind: K) -> bool {
+    pub fn maybe_peek(&mut self, kind: K) -> Result<bool, ParseError> {
         let last_offset = self.offset;
-        let r = self.next();
+        let r = self.next()?;
         if r.kind == kind {
-            return true;
+            return Ok(true);
         }
         self.offset = last_offset;
-        false
+        Ok(false)
     }
-    pub fn peek(&mut self) -> Token {
+    pub fn peek(&mut self) -> Result<Token, ParseError> {
         let last_offset = self.offset;
         let r = self.next();
         self.offset = last_offset;
         r
     }
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset;
+    }
+    fn error(&self, message: &str, offset: usize) -> ParseError {
+        let loc = Location {
+            start: offset,
+            stop: offset,
+            source_id: self.source_id,
+        };
+        ParseError::new(message, loc)
+    }
     fn eat_whitespace(&mut self) {
         let mut marker = 0;
         let mut offset = self.offset;
@@ -170,8 +185,8 @@ impl<'x> Lexer<'x> {
             */
         }
     }
-    fn read_eval_string(&mut self, path: bool) -> L<String> {
-        let mut ret = String::new();
+    fn read_eval_string(&mut self, path: bool) -> Result<L<EvalString>, ParseError> {
+        let mut ret = EvalString::default();
         let s = &mut ret;
 
         let start_offset = self.offset;
@@ -183,7 +198,7 @@ impl<'x> Lexer<'x> {
             // https://github.com/ninja-build/ninja/blob/master/src/lexer.in.cc
             /*!re2c
             [^$ :\r\n|\000]+ {
-              *s += &self.text_str[start..offset];
+              s.push_literal(&self.text_str[start..offset]);
               continue 'lex;
             }
             "\r\n" {
@@ -200,16 +215,16 @@ impl<'x> Lexer<'x> {
                 if self.text[start] == b'\n' {
                     break 'lex;
                 }
-                *s += &self.text_str[start..start + 1];
+                s.push_literal(&self.text_str[start..start + 1]);
                 continue 'lex;
               }
             }
             "$$" {
-              s.push('$');
+              s.push_literal("$");
               continue 'lex;
             }
             "$ " {
-              s.push(' ');
+              s.push_literal(" ");
               continue 'lex;
             }
             "$\r\n"[ ]* {
@@ -219,29 +234,25 @@ impl<'x> Lexer<'x> {
               continue 'lex;
             }
             "${"varname"}" {
-              *s += &self.text_str[start + 2..offset];
+              s.push_var(&self.text_str[start + 2..offset - 1]);
               continue 'lex;
             }
             "$"simple_varname {
-              *s += &self.text_str[start + 1..offset];
+              s.push_var(&self.text_str[start + 1..offset]);
               continue 'lex;
             }
             "$:" {
-              s.push(':');
+              s.push_literal(":");
               continue 'lex;
             }
             "$". {
-              // last_token_ = start;
-              panic!("bad $-escape (literal $ must be written as $$)");
+              return Err(self.error("bad $-escape (literal $ must be written as $$)", start));
             }
             nul {
-              // last_token_ = start;
-              panic!("unexpected EOF");
+              return Err(self.error("unexpected EOF", start));
             }
             [^] {
-              // last_token_ = start;
-              // return Error(DescribeLastError(), err);
-              panic!();
+              return Err(self.error("unexpected character", start));
             }
             */
         }
@@ -257,19 +268,19 @@ impl<'x> Lexer<'x> {
             self.eat_whitespace();
         }
         
-        L::new(ret, loc)
+        Ok(L::new(ret, loc))
     }
-    pub fn read_path(&mut self) -> L<String> {
+    pub fn read_path(&mut self) -> Result<L<EvalString>, ParseError> {
         self.read_eval_string(true)
     }
-    pub fn read_var_value(&mut self) -> L<String> {
+    pub fn read_var_value(&mut self) -> Result<L<EvalString>, ParseError> {
         self.read_eval_string(false)
     }
-    pub fn read_ident(&mut self) -> Location {
-        let next = self.next();
+    pub fn read_ident(&mut self) -> Result<Location, ParseError> {
+        let next = self.next()?;
         match next.kind {
-            K::Ident | K::Pool => next.loc,
-            _ => panic!("expected ident"),
+            K::Ident | K::Pool => Ok(next.loc),
+            _ => Err(ParseError::new("expected variable name", next.loc)),
         }
     }
 }
@@ -1,15 +1,63 @@
+mod build_log;
 mod changelist;
+mod compdb;
+mod cst;
+mod cycle;
+mod deps_log;
+mod diagnostic;
+mod diff;
+mod dot;
+mod dupbuild;
+mod dyndep;
+mod edit;
+mod eval;
+mod flatten;
+mod format;
+mod graph;
+mod journal;
+#[cfg(feature = "serde")]
+pub mod json;
 mod lexer;
+mod lint;
+mod merge;
 mod parser;
-use crate::lexer::{Location, Token};
+mod prune;
+#[cfg(feature = "recipe")]
+pub mod recipe;
+mod split;
+mod tools;
+mod validate;
+mod writer;
+use crate::lexer::Token;
 use crate::parser::parse;
-use changelist::ChangeList;
+pub use build_log::{BuildLog, BuildLogError, LogEntry};
+pub use changelist::ChangeList;
+#[cfg(feature = "regex")]
+pub use changelist::RewriteError;
+pub use compdb::{CompdbOptions, CompileCommand};
+pub use cst::{ItemKind, SyntaxItem, SyntaxKind, SyntaxToken, SyntaxTree};
+pub use cycle::{Cycle, CycleStep};
+pub use deps_log::{DepsEntry, DepsLog, DepsLogError};
+pub use diagnostic::Diagnostic;
+pub use diff::{DiffKind, EdgeDiff, ManifestDiff, PathDiff, RuleDiff, ValueDiff, VariableDiff};
+pub use dot::DotOptions;
+pub use dupbuild::DuplicateOutput;
+pub use dyndep::Dyndep;
+pub use eval::{EscapeError, EvalPart, EvalString};
+pub use format::FormatOptions;
 use fs_err as fs;
+pub use journal::{Journal, JournalError};
+pub use lexer::Location;
 use lexer::LOC_INVALID;
+pub use lint::{Lint, LintId};
+pub use merge::{MergeConflict, MergeItem};
 use slotmap::{new_key_type, SlotMap};
 use std::collections::HashMap;
+use std::io;
 use std::path::Path;
 use std::{borrow::Borrow, path::PathBuf};
+pub use tools::{InputsOptions, TargetsMode};
+pub use validate::ValidationError;
 
 struct Source {
     id: SourceId,
@@ -30,31 +78,48 @@ impl Source {
     fn text(&self) -> &str {
         &self.text[0..self.text.len() - 1]
     }
+    fn line_col(&self, offset: usize) -> (usize, usize) {
+        diagnostic::line_col(&self.text, offset)
+    }
 }
 
 #[derive(Default)]
 struct SourceManager {
     sources: Vec<&'static Source>, // TODO
+    /// Texts to use instead of reading the file with the same path from disk.
+    overrides: HashMap<PathBuf, String>,
 }
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
 struct SourceId(u32);
 
 impl SourceManager {
-    fn load<I: Into<PathBuf>>(&mut self, path: I) -> &'static Source {
-        fn inner(manager: &mut SourceManager, path: PathBuf) -> &'static Source {
+    fn with_overrides(overrides: HashMap<PathBuf, String>) -> SourceManager {
+        SourceManager {
+            sources: Vec::new(),
+            overrides,
+        }
+    }
+    fn load<I: Into<PathBuf>>(&mut self, path: I) -> io::Result<&'static Source> {
+        fn inner(manager: &mut SourceManager, path: PathBuf) -> io::Result<&'static Source> {
             let id: u32 = manager.sources.len().try_into().unwrap();
             let id = SourceId(id);
 
-            let mut text = fs::read_to_string(&path).unwrap();
+            let mut text = match manager.overrides.get(&path) {
+                Some(text) => text.clone(),
+                None => fs::read_to_string(&path)?,
+            };
             if text.as_bytes().contains(&b'\0') {
-                todo!("text can't contain zeros");
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "text can't contain zeros",
+                ));
             }
 
             text.push('\0');
             let source = Box::leak(Box::new(Source { id, text, path }));
             manager.sources.push(source);
-            manager.sources.last().unwrap()
+            Ok(manager.sources.last().unwrap())
         }
         inner(self, path.into())
     }
@@ -72,91 +137,630 @@ impl<T> L<T> {
     fn new(elem: T, loc: Location) -> Self {
         Self { elem, loc }
     }
+    /// Not written anywhere, e.g. added with the `Data` building functions.
+    fn synthetic(elem: T) -> Self {
+        Self::new(elem, LOC_INVALID)
+    }
+    pub fn loc(&self) -> Location {
+        self.loc
+    }
 }
 
 type LStr<'x> = L<&'x str>;
 
-#[derive(Default, Debug)]
+/// An unevaluated `name = value` line of a rule.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: L<String>,
+    pub value: L<EvalString>,
+}
+
+/// A variable assigned at the top level of a file or on a `build` statement;
+/// these are evaluated where they are written.
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: L<String>,
+    pub value: L<String>,
+    /// For top level variables, the file of the last assignment.
+    pub file: FileKey,
+}
+
+#[derive(Debug)]
 pub struct Rule<'x> {
     pub name: LStr<'x>,
-    // command: String,
-    // depfile: Option<String>,
-    // deps: Option<String>,
-    // description: Option<String>,
-    // restat: Option<String>,
-    // generator: Option<String>,
+    pub bindings: Vec<Binding>,
+    /// `None` for the builtin `phony`.
+    pub file: Option<FileKey>,
+}
+impl Rule<'_> {
+    pub fn binding(&self, name: &str) -> Option<&EvalString> {
+        self.bindings
+            .iter()
+            .find(|x| x.name.elem == name)
+            .map(|x| &x.value.elem)
+    }
+}
+
+#[derive(Debug)]
+pub struct Pool {
+    pub name: L<String>,
+    pub depth: L<u32>,
+    /// `None` for the builtin `console`.
+    pub file: Option<FileKey>,
 }
 
 new_key_type! {
     pub struct RuleKey;
     pub struct EdgeKey;
+    pub struct NodeKey;
+    pub struct PoolKey;
+    pub struct ScopeKey;
+    pub struct FileKey;
 }
 
-struct Edge {
-    rule: RuleKey,
+/// Where a path appears in a `build` statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathKind {
+    Out,
+    ImplicitOut,
+    In,
+    ImplicitIn,
+    OrderOnlyIn,
+    Validation,
+}
+impl PathKind {
+    pub const ALL: [PathKind; 6] = [
+        PathKind::Out,
+        PathKind::ImplicitOut,
+        PathKind::In,
+        PathKind::ImplicitIn,
+        PathKind::OrderOnlyIn,
+        PathKind::Validation,
+    ];
+    pub fn is_output(self) -> bool {
+        matches!(self, PathKind::Out | PathKind::ImplicitOut)
+    }
+}
+
+#[derive(Debug)]
+pub struct Node {
+    pub path: String,
+    /// Every place the path is written.
+    pub locs: Vec<Location>,
+    /// The first edge producing the node.
+    pub in_edge: Option<EdgeKey>,
+    /// Edges having the node as an explicit, implicit or order-only input.
+    pub out_edges: Vec<EdgeKey>,
+}
+
+#[derive(Debug)]
+pub struct Edge {
+    pub rule: RuleKey,
     rule_loc: Location,
-    // ins: Vec<String>,
-    // outs: Vec<L<String>>,
+    /// The `build` keyword.
+    pub loc: Location,
+    pub file: FileKey,
+    pub scope: ScopeKey,
+    pub outs: Vec<L<NodeKey>>,
+    pub implicit_outs: Vec<L<NodeKey>>,
+    pub ins: Vec<L<NodeKey>>,
+    pub implicit_ins: Vec<L<NodeKey>>,
+    pub order_only_ins: Vec<L<NodeKey>>,
+    pub validations: Vec<L<NodeKey>>,
+    pub bindings: Vec<Variable>,
+    /// What its dyndep file adds, once loaded with [`Data::load_dyndep`].
+    pub dyndep: Option<Dyndep>,
+}
+impl Edge {
+    pub fn paths(&self, kind: PathKind) -> &[L<NodeKey>] {
+        match kind {
+            PathKind::Out => &self.outs,
+            PathKind::ImplicitOut => &self.implicit_outs,
+            PathKind::In => &self.ins,
+            PathKind::ImplicitIn => &self.implicit_ins,
+            PathKind::OrderOnlyIn => &self.order_only_ins,
+            PathKind::Validation => &self.validations,
+        }
+    }
+    fn paths_mut(&mut self, kind: PathKind) -> &mut Vec<L<NodeKey>> {
+        match kind {
+            PathKind::Out => &mut self.outs,
+            PathKind::ImplicitOut => &mut self.implicit_outs,
+            PathKind::In => &mut self.ins,
+            PathKind::ImplicitIn => &mut self.implicit_ins,
+            PathKind::OrderOnlyIn => &mut self.order_only_ins,
+            PathKind::Validation => &mut self.validations,
+        }
+    }
+    /// Every path of the edge with its kind, in the order they are written.
+    pub fn all_paths(&self) -> impl Iterator<Item = (PathKind, &L<NodeKey>)> {
+        PathKind::ALL
+            .into_iter()
+            .flat_map(|kind| self.paths(kind).iter().map(move |x| (kind, x)))
+    }
+    /// The inputs ninja waits for, including the implicit inputs added by a
+    /// loaded dyndep file.
+    pub fn inputs(&self) -> impl Iterator<Item = (PathKind, NodeKey)> + '_ {
+        let dyndep = self.dyndep.iter().flat_map(|x| &x.implicit_ins);
+        [PathKind::In, PathKind::ImplicitIn]
+            .into_iter()
+            .flat_map(|kind| self.paths(kind).iter().map(move |x| (kind, x.elem)))
+            .chain(dyndep.map(|&x| (PathKind::ImplicitIn, x)))
+            .chain(
+                self.order_only_ins
+                    .iter()
+                    .map(|x| (PathKind::OrderOnlyIn, x.elem)),
+            )
+    }
+    /// The outputs, including the implicit outputs added by a loaded dyndep
+    /// file.
+    pub fn outputs(&self) -> impl Iterator<Item = NodeKey> + '_ {
+        let dyndep = self.dyndep.iter().flat_map(|x| &x.implicit_outs);
+        self.outs
+            .iter()
+            .chain(&self.implicit_outs)
+            .map(|x| x.elem)
+            .chain(dyndep.copied())
+    }
+    pub fn binding(&self, name: &str) -> Option<&str> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|x| x.name.elem == name)
+            .map(|x| x.value.elem.as_str())
+    }
+}
+
+/// Variables and rules are looked up in the scope of their file, then in the
+/// parent scopes. Every `subninja` file has its own scope.
+#[derive(Debug)]
+pub struct Scope<'x> {
+    pub parent: Option<ScopeKey>,
+    /// Final values, in the order of their first assignment.
+    pub vars: Vec<Variable>,
+    rules: HashMap<&'x str, RuleKey>,
+}
+impl Scope<'_> {
+    fn new(parent: Option<ScopeKey>) -> Self {
+        Scope {
+            parent,
+            vars: Vec::new(),
+            rules: HashMap::new(),
+        }
+    }
+    pub fn var(&self, name: &str) -> Option<&Variable> {
+        self.vars.iter().find(|x| x.name.elem == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Root,
+    Include,
+    Subninja,
+}
+
+#[derive(Debug)]
+pub struct File {
+    /// The evaluated path, as written in the `include` or `subninja`
+    /// statement.
+    pub path: L<String>,
+    pub kind: FileKind,
+    pub parent: Option<FileKey>,
+    pub scope: ScopeKey,
+    /// `include` and `subninja` statements of the file.
+    pub children: Vec<FileKey>,
+}
+
+#[derive(Debug)]
+pub struct DefaultStatement {
+    pub file: FileKey,
+    pub targets: Vec<L<NodeKey>>,
 }
 
-#[derive(Default)]
 pub struct Data<'x> {
     pub rules: SlotMap<RuleKey, Rule<'x>>,
-    edges: SlotMap<EdgeKey, Edge>,
-    pub nodes: HashMap<String, Vec<Location>>,
+    pub pools: SlotMap<PoolKey, Pool>,
+    pub edges: SlotMap<EdgeKey, Edge>,
+    pub nodes: SlotMap<NodeKey, Node>,
+    pub scopes: SlotMap<ScopeKey, Scope<'x>>,
+    pub files: SlotMap<FileKey, File>,
+    pub defaults: Vec<DefaultStatement>,
     //
-    rules_by_name: HashMap<&'x str, RuleKey>,
-    //
-    vars: HashMap<String, L<String>>,
-    default: Option<L<String>>,
+    nodes_by_path: HashMap<String, NodeKey>,
+    pools_by_name: HashMap<String, PoolKey>,
+    root_file: FileKey,
 }
 impl<'x> Data<'x> {
-    fn new() -> Data<'x> {
-        let mut rules = SlotMap::with_key();
-        let phony = rules.insert(Rule {
-            name: L::new("phony", LOC_INVALID),
-            ..Rule::default()
+    /// An empty manifest, with the builtin `phony` rule and `console` pool and
+    /// a root file named `build.ninja`.
+    pub fn new() -> Data<'x> {
+        let mut scopes = SlotMap::with_key();
+        let root_scope = scopes.insert(Scope::new(None));
+        let mut files = SlotMap::with_key();
+        let root_file = files.insert(File {
+            path: L::synthetic("build.ninja".to_string()),
+            kind: FileKind::Root,
+            parent: None,
+            scope: root_scope,
+            children: Vec::new(),
         });
 
-        let rules_by_name = HashMap::from([("phony", phony)]);
-
-        Data {
-            rules,
+        let mut data = Data {
+            rules: SlotMap::with_key(),
+            pools: SlotMap::with_key(),
             edges: SlotMap::with_key(),
-            nodes: HashMap::new(),
+            nodes: SlotMap::with_key(),
+            scopes,
+            files,
+            defaults: Vec::new(),
             //
-            rules_by_name,
-            //
-            vars: HashMap::new(),
-            default: None,
+            nodes_by_path: HashMap::new(),
+            pools_by_name: HashMap::new(),
+            root_file,
+        };
+
+        let phony = data.rules.insert(Rule {
+            name: L::synthetic("phony"),
+            bindings: Vec::new(),
+            file: None,
+        });
+        data.scopes[root_scope].rules.insert("phony", phony);
+        data.insert_pool(Pool {
+            name: L::synthetic("console".to_string()),
+            depth: L::synthetic(1),
+            file: None,
+        });
+
+        data
+    }
+    pub fn root_file(&self) -> FileKey {
+        self.root_file
+    }
+    pub fn root_scope(&self) -> ScopeKey {
+        self.files[self.root_file].scope
+    }
+    pub fn node(&self, path: &str) -> Option<NodeKey> {
+        self.nodes_by_path.get(path).copied()
+    }
+    pub fn pool(&self, name: &str) -> Option<PoolKey> {
+        self.pools_by_name.get(name).copied()
+    }
+    /// Looks up a rule visible from `scope`.
+    pub fn rule(&self, scope: ScopeKey, name: &str) -> Option<RuleKey> {
+        self.scope_chain(scope)
+            .find_map(|x| self.scopes[x].rules.get(name).copied())
+    }
+    /// Looks up a variable visible from `scope`.
+    pub fn var(&self, scope: ScopeKey, name: &str) -> Option<&Variable> {
+        self.scope_chain(scope)
+            .find_map(|x| self.scopes[x].var(name))
+    }
+    /// `scope` and its parents.
+    pub fn scope_chain(&self, scope: ScopeKey) -> impl Iterator<Item = ScopeKey> + '_ {
+        std::iter::successors(Some(scope), |&x| self.scopes[x].parent)
+    }
+    /// The value of a variable for the paths and bindings of `edge`: its own
+    /// bindings, then its scope.
+    pub fn edge_var(&self, edge: EdgeKey, name: &str) -> Option<&str> {
+        let edge = &self.edges[edge];
+        edge.binding(name)
+            .or_else(|| self.var(edge.scope, name).map(|x| x.value.elem.as_str()))
+    }
+    /// Evaluates a variable the way ninja does when running `edge`: `$in`,
+    /// `$in_newline` and `$out` are its shell escaped explicit paths,
+    /// everything else comes from its bindings, then its rule's bindings, then
+    /// its scope. Rule bindings are evaluated in the same way; a reference
+    /// cycle evaluates to nothing.
+    pub fn evaluate_edge(&self, edge: EdgeKey, name: &str) -> String {
+        self.evaluate_edge_impl(edge, name, true, &mut Vec::new())
+    }
+    /// Like [`Data::evaluate_edge`], but with the paths of `$in` and `$out`
+    /// as they are, which is how ninja evaluates `depfile`, `rspfile` and
+    /// `dyndep`.
+    pub fn evaluate_edge_unescaped(&self, edge: EdgeKey, name: &str) -> String {
+        self.evaluate_edge_impl(edge, name, false, &mut Vec::new())
+    }
+    fn evaluate_edge_impl(
+        &self,
+        key: EdgeKey,
+        name: &str,
+        escape: bool,
+        stack: &mut Vec<String>,
+    ) -> String {
+        let edge = &self.edges[key];
+        let paths = |kind, separator| {
+            let paths = edge.paths(kind).iter().map(|x| &self.nodes[x.elem].path);
+            match escape {
+                true => paths.map(|x| eval::shell_escape(x)).collect::<Vec<_>>(),
+                false => paths.cloned().collect(),
+            }
+            .join(separator)
+        };
+        match name {
+            "in" => return paths(PathKind::In, " "),
+            "in_newline" => return paths(PathKind::In, "\n"),
+            "out" => return paths(PathKind::Out, " "),
+            _ => {}
+        }
+        if let Some(value) = edge.binding(name) {
+            return value.to_string();
+        }
+        if let Some(value) = self.rules[edge.rule].binding(name) {
+            if stack.iter().any(|x| x == name) {
+                return String::new();
+            }
+            stack.push(name.to_string());
+            let ret = value.evaluate(|x| self.evaluate_edge_impl(key, x, escape, stack));
+            stack.pop();
+            return ret;
+        }
+        self.var(edge.scope, name)
+            .map(|x| x.value.elem.clone())
+            .unwrap_or_default()
+    }
+
+    /// Adds an `include` or `subninja` statement to the end of `parent`.
+    pub fn add_file(&mut self, parent: FileKey, path: &str, kind: FileKind) -> FileKey {
+        self.insert_file(parent, L::synthetic(path.to_string()), kind)
+    }
+    /// Assigns a top level variable in `file`.
+    pub fn set_var(&mut self, file: FileKey, name: &str, value: &str) {
+        self.insert_var(
+            file,
+            L::synthetic(name.to_string()),
+            L::synthetic(value.to_string()),
+        );
+    }
+    /// Returns `None` if the scope of `file` already has a rule with that
+    /// name.
+    pub fn add_rule(
+        &mut self,
+        file: FileKey,
+        name: &'x str,
+        bindings: Vec<(String, EvalString)>,
+    ) -> Option<RuleKey> {
+        let bindings = bindings
+            .into_iter()
+            .map(|(name, value)| Binding {
+                name: L::synthetic(name),
+                value: L::synthetic(value),
+            })
+            .collect();
+        self.insert_rule(Rule {
+            name: L::synthetic(name),
+            bindings,
+            file: Some(file),
+        })
+    }
+    /// Returns `None` if there is already a pool with that name.
+    pub fn add_pool(&mut self, file: FileKey, name: &str, depth: u32) -> Option<PoolKey> {
+        self.insert_pool(Pool {
+            name: L::synthetic(name.to_string()),
+            depth: L::synthetic(depth),
+            file: Some(file),
+        })
+    }
+    /// Adds a `build` statement without paths or bindings; see
+    /// [`Data::add_edge_path`] and [`Data::add_edge_binding`].
+    pub fn add_edge(&mut self, file: FileKey, rule: RuleKey) -> EdgeKey {
+        let scope = self.files[file].scope;
+        self.edges
+            .insert(Edge::new(rule, LOC_INVALID, LOC_INVALID, file, scope))
+    }
+    pub fn add_edge_path(&mut self, edge: EdgeKey, kind: PathKind, path: &str) -> NodeKey {
+        self.insert_edge_path(edge, kind, L::synthetic(path.to_string()))
+    }
+    pub fn add_edge_binding(&mut self, edge: EdgeKey, name: &str, value: &str) {
+        let file = self.edges[edge].file;
+        self.edges[edge].bindings.push(Variable {
+            name: L::synthetic(name.to_string()),
+            value: L::synthetic(value.to_string()),
+            file,
+        });
+    }
+    pub fn add_default(&mut self, file: FileKey, targets: &[&str]) {
+        let targets = targets
+            .iter()
+            .map(|x| L::synthetic(self.insert_node(L::synthetic(x.to_string()))))
+            .collect();
+        self.defaults.push(DefaultStatement { file, targets });
+    }
+
+    fn insert_file(&mut self, parent: FileKey, path: L<String>, kind: FileKind) -> FileKey {
+        let parent_scope = self.files[parent].scope;
+        let scope = match kind {
+            FileKind::Subninja => self.scopes.insert(Scope::new(Some(parent_scope))),
+            _ => parent_scope,
+        };
+        let file = self.files.insert(File {
+            path,
+            kind,
+            parent: Some(parent),
+            scope,
+            children: Vec::new(),
+        });
+        self.files[parent].children.push(file);
+        file
+    }
+    fn insert_var(&mut self, file: FileKey, name: L<String>, value: L<String>) {
+        let scope = &mut self.scopes[self.files[file].scope];
+        let var = Variable { name, value, file };
+        match scope.vars.iter_mut().find(|x| x.name.elem == var.name.elem) {
+            Some(x) => *x = var,
+            None => scope.vars.push(var),
         }
     }
+    fn insert_rule(&mut self, rule: Rule<'x>) -> Option<RuleKey> {
+        let scope = self.files[rule.file?].scope;
+        if self.scopes[scope].rules.contains_key(rule.name.elem) {
+            return None;
+        }
+        let name = rule.name.elem;
+        let key = self.rules.insert(rule);
+        self.scopes[scope].rules.insert(name, key);
+        Some(key)
+    }
+    fn insert_pool(&mut self, pool: Pool) -> Option<PoolKey> {
+        if self.pools_by_name.contains_key(&pool.name.elem) {
+            return None;
+        }
+        let name = pool.name.elem.clone();
+        let key = self.pools.insert(pool);
+        self.pools_by_name.insert(name, key);
+        Some(key)
+    }
+    fn insert_node(&mut self, path: L<String>) -> NodeKey {
+        let key = match self.nodes_by_path.get(&path.elem) {
+            Some(&key) => key,
+            None => {
+                let key = self.nodes.insert(Node {
+                    path: path.elem.clone(),
+                    locs: Vec::new(),
+                    in_edge: None,
+                    out_edges: Vec::new(),
+                });
+                self.nodes_by_path.insert(path.elem, key);
+                key
+            }
+        };
+        if path.loc != LOC_INVALID {
+            self.nodes[key].locs.push(path.loc);
+        }
+        key
+    }
+    fn insert_edge_path(&mut self, edge: EdgeKey, kind: PathKind, path: L<String>) -> NodeKey {
+        let loc = path.loc;
+        let node = self.insert_node(path);
+        match kind {
+            PathKind::Out | PathKind::ImplicitOut => {
+                self.nodes[node].in_edge.get_or_insert(edge);
+            }
+            PathKind::In | PathKind::ImplicitIn | PathKind::OrderOnlyIn => {
+                self.nodes[node].out_edges.push(edge);
+            }
+            PathKind::Validation => {}
+        }
+        self.edges[edge].paths_mut(kind).push(L::new(node, loc));
+        node
+    }
+}
+impl Default for Data<'_> {
+    fn default() -> Self {
+        Data::new()
+    }
+}
+impl Edge {
+    fn new(
+        rule: RuleKey,
+        rule_loc: Location,
+        loc: Location,
+        file: FileKey,
+        scope: ScopeKey,
+    ) -> Edge {
+        Edge {
+            rule,
+            rule_loc,
+            loc,
+            file,
+            scope,
+            outs: Vec::new(),
+            implicit_outs: Vec::new(),
+            ins: Vec::new(),
+            implicit_ins: Vec::new(),
+            order_only_ins: Vec::new(),
+            validations: Vec::new(),
+            bindings: Vec::new(),
+            dyndep: None,
+        }
+    }
+}
+
+/// What to do when several `build` statements generate the same path, like
+/// ninja's `-w dupbuild=...`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DupBuild {
+    /// Keep the path as an output of every statement; the first one is its
+    /// [`Node::in_edge`]. See [`Data::duplicate_outputs`].
+    #[default]
+    Allow,
+    /// Like ninja with `-w dupbuild=warn`: drop the path from the outputs of
+    /// the later statements, and the statements left without outputs, with a
+    /// warning.
+    Warn,
+    /// Fail like ninja does by default.
+    Err,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadOptions {
+    pub dupbuild: DupBuild,
 }
 
 pub struct Ninja {
     sm: SourceManager,
     data: Data<'static>,
+    options: LoadOptions,
+    warnings: Vec<Diagnostic>,
 }
 
 impl Ninja {
-    fn load_impl(path: &Path) -> Ninja {
-        let mut sm = SourceManager::default();
+    fn load_impl(
+        mut sm: SourceManager,
+        path: &Path,
+        options: LoadOptions,
+    ) -> Result<Ninja, Diagnostic> {
         let mut data = Data::new();
+        let mut warnings = Vec::new();
 
-        parse(&mut sm, &mut data, path);
+        parse(&mut sm, &mut data, path, options, &mut warnings)?;
 
-        Ninja { sm, data }
+        Ok(Ninja {
+            sm,
+            data,
+            options,
+            warnings,
+        })
     }
     pub fn load<P: AsRef<Path>>(path: P) -> Ninja {
-        Self::load_impl(path.as_ref())
+        Self::try_load(path).unwrap_or_else(|e| panic!("{e}"))
     }
     pub fn load_folder<P: AsRef<Path>>(path: P) -> Ninja {
-        Self::load_impl(path.as_ref().join("build.ninja").as_path())
+        Self::load(path.as_ref().join("build.ninja"))
     }
-    pub fn data(&self) -> &Data {
+    pub fn try_load<P: AsRef<Path>>(path: P) -> Result<Ninja, Diagnostic> {
+        Self::try_load_with(path, LoadOptions::default())
+    }
+    pub fn try_load_with<P: AsRef<Path>>(
+        path: P,
+        options: LoadOptions,
+    ) -> Result<Ninja, Diagnostic> {
+        Self::load_impl(SourceManager::default(), path.as_ref(), options)
+    }
+    /// The warnings of loading the manifest, e.g. for [`DupBuild::Warn`].
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+    pub fn data(&self) -> &Data<'_> {
         &self.data
     }
-    pub fn change(&self) -> ChangeList {
+    pub fn change(&self) -> ChangeList<'_> {
         ChangeList::new(self)
     }
+    /// Paths of the manifest files, in the order they were loaded.
+    pub fn sources(&self) -> impl Iterator<Item = &Path> {
+        self.sm.sources.iter().map(|x| x.path.as_path())
+    }
+    /// The lossless syntax tree of one of the manifest files.
+    pub fn syntax_tree<P: AsRef<Path>>(&self, path: P) -> Result<SyntaxTree, Diagnostic> {
+        let path = path.as_ref();
+        let Some(source) = self.sm.sources.iter().find(|x| x.path == path) else {
+            return Err(Diagnostic::for_file(path, "not part of this manifest"));
+        };
+        SyntaxTree::build(source.text(), source.id)
+            .map_err(|e| Diagnostic::from_parse_error(&self.sm, e))
+    }
+    fn root_path(&self) -> &Path {
+        &self.sm.get(SourceId(0)).path
+    }
 }
@@ -0,0 +1,399 @@
+use crate::{
+    lexer::LOC_INVALID, parser::RULE_VARS, Diagnostic, EdgeKey, EvalPart, EvalString, Location,
+    Ninja, PathKind, RuleKey, SyntaxKind, SyntaxTree,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+
+/// What a [`Lint`] is about. The ids are stable, so they can be used in
+/// suppression comments:
+///
+/// ```ninja
+/// # ninja-lint: allow(unused-rule, missing-description)
+/// rule unused
+///   command = true
+/// ```
+///
+/// `allow(...)` applies to the next line that isn't blank or a comment;
+/// `allow-file(...)` applies to the whole file it's in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintId {
+    /// A rule no edge uses.
+    UnusedRule,
+    /// A top level variable nothing references, or a binding of a `build`
+    /// statement that neither its rule nor its paths reference.
+    UnusedVariable,
+    /// An input no edge generates that doesn't exist either. Paths are
+    /// relative to the working directory, as when loading the manifest.
+    MissingInput,
+    /// A `phony` edge without inputs.
+    EmptyPhony,
+    /// An edge having one of its outputs as an input.
+    OutputIsInput,
+    /// A rule without `description`, so ninja prints the whole command.
+    MissingDescription,
+    /// `deps` and `depfile` settings ninja rejects or ignores.
+    DepsConfig,
+}
+
+impl LintId {
+    pub const ALL: [LintId; 7] = [
+        LintId::UnusedRule,
+        LintId::UnusedVariable,
+        LintId::MissingInput,
+        LintId::EmptyPhony,
+        LintId::OutputIsInput,
+        LintId::MissingDescription,
+        LintId::DepsConfig,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LintId::UnusedRule => "unused-rule",
+            LintId::UnusedVariable => "unused-variable",
+            LintId::MissingInput => "missing-input",
+            LintId::EmptyPhony => "empty-phony",
+            LintId::OutputIsInput => "output-is-input",
+            LintId::MissingDescription => "missing-description",
+            LintId::DepsConfig => "deps-config",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LintId> {
+        LintId::ALL.into_iter().find(|x| x.as_str() == name)
+    }
+}
+
+impl fmt::Display for LintId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A finding of [`Ninja::lint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub id: LintId,
+    pub loc: Location,
+    pub diagnostic: Diagnostic,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.diagnostic, self.id)
+    }
+}
+
+/// Variables ninja reads itself rather than through a reference.
+const BUILTIN_VARS: [&str; 2] = ["builddir", "ninja_required_version"];
+
+impl Ninja {
+    /// Checks the manifest for likely mistakes, in the order they are
+    /// written, leaving out the suppressed ones. Inputs are looked up on
+    /// disk relative to the working directory, like ninja does.
+    ///
+    /// Only what is written in the manifest is checked; paths added by
+    /// dyndep files or the `Data` building functions have no location.
+    pub fn lint(&self) -> Vec<Lint> {
+        let mut findings = Vec::new();
+        let mut push = |id: LintId, loc: Location, message: String| {
+            if loc != LOC_INVALID {
+                findings.push((id, loc, message));
+            }
+        };
+        self.lint_rules(&mut push);
+        self.lint_variables(&mut push);
+        self.lint_edges(&mut push);
+        self.lint_deps(&mut push);
+
+        let suppressions: Vec<_> = self
+            .sm
+            .sources
+            .iter()
+            .map(|x| Suppressions::parse(x.text()))
+            .collect();
+        findings.sort_by_key(|x| (x.1.source_id, x.1.start));
+        findings.dedup();
+        findings
+            .into_iter()
+            .filter_map(|(id, loc, message)| {
+                let source = self.sm.get(loc.source_id);
+                let (line, _) = source.line_col(loc.start);
+                if suppressions[loc.source_id.0 as usize].allows(line, id) {
+                    return None;
+                }
+                let diagnostic = Diagnostic::at(&self.sm, loc, message);
+                Some(Lint {
+                    id,
+                    loc,
+                    diagnostic,
+                })
+            })
+            .collect()
+    }
+
+    fn lint_rules<F: FnMut(LintId, Location, String)>(&self, push: &mut F) {
+        let data = &self.data;
+        let mut edges: HashMap<RuleKey, Vec<EdgeKey>> = HashMap::new();
+        for (key, edge) in &data.edges {
+            edges.entry(edge.rule).or_default().push(key);
+        }
+        for (key, rule) in &data.rules {
+            if rule.file.is_none() {
+                continue;
+            }
+            let name = rule.name.elem;
+            let Some(edges) = edges.get(&key) else {
+                push(
+                    LintId::UnusedRule,
+                    rule.name.loc(),
+                    format!("rule `{}` is never used", name),
+                );
+                continue;
+            };
+            let described = |&x: &EdgeKey| !data.evaluate_edge(x, "description").is_empty();
+            if rule.binding("description").is_none() && !edges.iter().all(described) {
+                push(
+                    LintId::MissingDescription,
+                    rule.name.loc(),
+                    format!("rule `{}` has no description", name),
+                );
+            }
+        }
+    }
+
+    fn lint_variables<F: FnMut(LintId, Location, String)>(&self, push: &mut F) {
+        let data = &self.data;
+        let mut referenced = HashSet::new();
+        for source in &self.sm.sources {
+            // Comments are skipped, so commented out uses don't count.
+            let Ok(tree) = SyntaxTree::build(source.text(), source.id) else {
+                continue;
+            };
+            for item in tree.items() {
+                item.for_each_token(&mut |x| {
+                    if matches!(x.kind, SyntaxKind::Path | SyntaxKind::Value) {
+                        referenced.extend(references(tree.str(x)));
+                    }
+                });
+            }
+        }
+        for scope in data.scopes.values() {
+            for var in &scope.vars {
+                let name = var.name.elem.as_str();
+                if !referenced.contains(&var.name.elem)
+                    && !BUILTIN_VARS.contains(&name)
+                    && !RULE_VARS.contains(&name)
+                {
+                    push(
+                        LintId::UnusedVariable,
+                        var.name.loc(),
+                        format!("variable `{}` is never used", name),
+                    );
+                }
+            }
+        }
+
+        for edge in data.edges.values() {
+            let rule = &data.rules[edge.rule];
+            let mut referenced: HashSet<&str> = rule
+                .bindings
+                .iter()
+                .flat_map(|x| x.value.elem.parts())
+                .filter_map(|x| match x {
+                    EvalPart::Var(name) => Some(name.as_str()),
+                    EvalPart::Literal(_) => None,
+                })
+                .collect();
+            let path_references: Vec<_> = edge
+                .all_paths()
+                .filter(|(_, path)| path.loc() != LOC_INVALID)
+                .flat_map(|(_, path)| {
+                    let source = self.sm.get(path.loc().source_id);
+                    references(source.str_loc(path.loc()))
+                })
+                .collect();
+            referenced.extend(path_references.iter().map(String::as_str));
+            for var in &edge.bindings {
+                let name = var.name.elem.as_str();
+                if !referenced.contains(name) && !RULE_VARS.contains(&name) {
+                    push(
+                        LintId::UnusedVariable,
+                        var.name.loc(),
+                        format!(
+                            "variable `{}` is not used by rule `{}`",
+                            name, rule.name.elem
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn lint_edges<F: FnMut(LintId, Location, String)>(&self, push: &mut F) {
+        let data = &self.data;
+        let mut checked = HashSet::new();
+        for edge in data.edges.values() {
+            let phony = data.rules[edge.rule].file.is_none();
+            if phony && edge.inputs().next().is_none() {
+                push(
+                    LintId::EmptyPhony,
+                    edge.loc,
+                    "phony edge without inputs".to_string(),
+                );
+            }
+            let outputs: HashSet<_> = edge.outputs().collect();
+            for (kind, path) in edge.all_paths() {
+                if kind.is_output() {
+                    continue;
+                }
+                let node = &data.nodes[path.elem];
+                if kind != PathKind::Validation && outputs.contains(&path.elem) {
+                    push(
+                        LintId::OutputIsInput,
+                        path.loc(),
+                        format!("`{}` is both an output and an input", node.path),
+                    );
+                }
+                if node.in_edge.is_none()
+                    && checked.insert(path.elem)
+                    && !Path::new(&node.path).exists()
+                {
+                    push(
+                        LintId::MissingInput,
+                        path.loc(),
+                        format!("`{}` is neither generated nor present", node.path),
+                    );
+                }
+            }
+        }
+    }
+
+    fn lint_deps<F: FnMut(LintId, Location, String)>(&self, push: &mut F) {
+        let data = &self.data;
+        for (key, edge) in &data.edges {
+            if data.rules[edge.rule].file.is_none() {
+                continue;
+            }
+            let deps = data.evaluate_edge(key, "deps");
+            let depfile = data.evaluate_edge_unescaped(key, "depfile");
+            let deps_loc = self.binding_loc(key, "deps");
+            match deps.as_str() {
+                "" | "gcc" | "msvc" => {}
+                _ => push(
+                    LintId::DepsConfig,
+                    deps_loc,
+                    format!("unknown deps type `{}`", deps),
+                ),
+            }
+            if deps == "gcc" && depfile.is_empty() {
+                push(
+                    LintId::DepsConfig,
+                    deps_loc,
+                    "`deps = gcc` without a `depfile`".to_string(),
+                );
+            }
+            if deps == "msvc" && !depfile.is_empty() {
+                push(
+                    LintId::DepsConfig,
+                    self.binding_loc(key, "depfile"),
+                    "`depfile` is ignored with `deps = msvc`".to_string(),
+                );
+            }
+            if deps != "msvc" && !data.evaluate_edge(key, "msvc_deps_prefix").is_empty() {
+                push(
+                    LintId::DepsConfig,
+                    self.binding_loc(key, "msvc_deps_prefix"),
+                    "`msvc_deps_prefix` is ignored without `deps = msvc`".to_string(),
+                );
+            }
+            if !deps.is_empty() && edge.outs.len() > 1 {
+                push(
+                    LintId::DepsConfig,
+                    edge.loc,
+                    "ninja doesn't support `deps` with multiple explicit outputs".to_string(),
+                );
+            }
+        }
+    }
+
+    /// Where the variable `name` of `edge` is set: on the edge, its rule or
+    /// its scope, or else the edge itself.
+    fn binding_loc(&self, edge: EdgeKey, name: &str) -> Location {
+        let data = &self.data;
+        let edge = &data.edges[edge];
+        let rule = &data.rules[edge.rule];
+        edge.bindings
+            .iter()
+            .rev()
+            .find(|x| x.name.elem == name)
+            .map(|x| x.name.loc())
+            .or_else(|| {
+                let binding = rule.bindings.iter().find(|x| x.name.elem == name);
+                binding.map(|x| x.name.loc())
+            })
+            .or_else(|| data.var(edge.scope, name).map(|x| x.name.loc()))
+            .filter(|&x| x != LOC_INVALID)
+            .unwrap_or(edge.loc)
+    }
+}
+
+/// The `# ninja-lint:` comments of one file.
+#[derive(Default)]
+struct Suppressions {
+    file: HashSet<LintId>,
+    lines: HashMap<usize, HashSet<LintId>>,
+}
+
+impl Suppressions {
+    fn parse(text: &str) -> Suppressions {
+        let mut ret = Suppressions::default();
+        let mut pending = HashSet::new();
+        for (n, line) in text.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let Some(comment) = trimmed.strip_prefix('#') else {
+                if !pending.is_empty() {
+                    ret.lines.insert(n + 1, std::mem::take(&mut pending));
+                }
+                continue;
+            };
+            let Some(directive) = comment.trim().strip_prefix("ninja-lint:") else {
+                continue;
+            };
+            let directive = directive.trim();
+            let (ids, target) = if let Some(x) = directive.strip_prefix("allow-file") {
+                (x, &mut ret.file)
+            } else if let Some(x) = directive.strip_prefix("allow") {
+                (x, &mut pending)
+            } else {
+                continue;
+            };
+            let ids = ids
+                .trim()
+                .strip_prefix('(')
+                .and_then(|x| x.strip_suffix(')'));
+            let ids = ids.unwrap_or_default().split(',');
+            target.extend(ids.filter_map(|x| LintId::from_name(x.trim())));
+        }
+        ret
+    }
+
+    fn allows(&self, line: usize, id: LintId) -> bool {
+        self.file.contains(&id) || self.lines.get(&line).is_some_and(|x| x.contains(&id))
+    }
+}
+
+/// The names of the variables referenced by a path or value token, or by
+/// the part of it between two continuations.
+pub(crate) fn references(text: &str) -> Vec<String> {
+    match EvalString::parse_value(text) {
+        Ok(value) => value.variables().map(str::to_string).collect(),
+        Err(_) => Vec::new(),
+    }
+}
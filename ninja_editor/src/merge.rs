@@ -0,0 +1,628 @@
+use crate::{
+    diff::{
+        diff_paths, diff_rule, diff_values, edge_outputs, match_edges, scope_name, DiffKind,
+        PathDiff, RuleDiff, ValueDiff, VariableDiff,
+    },
+    edit::{file_source, scope_file, trim_line_break},
+    lexer::{Location, LOC_INVALID},
+    writer::{write_edge, write_rule},
+    ChangeList, Data, Diagnostic, EdgeKey, EscapeError, EvalString, FileKind, Ninja, ScopeKey,
+    Variable,
+};
+use std::fmt;
+
+/// What a [`MergeConflict`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeItem {
+    Rule,
+    Edge,
+    Variable,
+}
+
+/// An edit of the patched manifest that wasn't replayed by
+/// [`ChangeList::merge`], because the regenerated manifest changed the same
+/// thing differently, or because the manifest doesn't load with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub item: MergeItem,
+    /// The `subninja` file whose scope has the rule or variable; `None` for
+    /// the root scope and for edges.
+    pub scope: Option<String>,
+    /// The name of the rule or variable, or the outputs of the edge.
+    pub name: String,
+    /// Why the manifest doesn't load with the edit; `None` when both sides
+    /// changed the item.
+    pub error: Option<Diagnostic>,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let item = match self.item {
+            MergeItem::Rule => "rule",
+            MergeItem::Edge => "build",
+            MergeItem::Variable => "variable",
+        };
+        match &self.error {
+            Some(_) => write!(f, "replaying {} `{}`", item, self.name)?,
+            None => write!(f, "both sides changed {} `{}`", item, self.name)?,
+        }
+        if let Some(scope) = &self.scope {
+            write!(f, " in {}", scope)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, " breaks the manifest: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// A change to a `build` statement, with what a text edit needs: the
+/// bindings are compared by their evaluated values.
+#[derive(Debug, PartialEq, Eq)]
+struct EdgeEdit {
+    kind: DiffKind,
+    outputs: Vec<String>,
+    rule: Option<ValueDiff>,
+    paths: Vec<PathDiff>,
+    bindings: Vec<ValueDiff>,
+}
+
+/// The statement level changes from one manifest to another.
+struct Edits {
+    rules: Vec<RuleDiff>,
+    /// With the edge in the original manifest, unless it was added, and in
+    /// the changed one, unless it was removed.
+    edges: Vec<(Option<EdgeKey>, Option<EdgeKey>, EdgeEdit)>,
+    variables: Vec<VariableDiff>,
+}
+
+impl Edits {
+    fn new(old: &Ninja, new: &Ninja) -> Result<Edits, EscapeError> {
+        let diff = old.diff(new)?;
+        let (old, new) = (&old.data, &new.data);
+        fn bindings<'a>(data: &'a Data, edge: EdgeKey) -> Vec<(&'a str, String)> {
+            let bindings = data.edges[edge].bindings.iter();
+            bindings
+                .map(|x| (x.name.elem.as_str(), x.value.elem.clone()))
+                .collect()
+        }
+
+        let matches = match_edges(old, new);
+        let mut edges = Vec::new();
+        for (old_key, new_key) in matches.matched {
+            let edit = EdgeEdit {
+                kind: DiffKind::Changed,
+                outputs: edge_outputs(new, new_key),
+                rule: diff_rule(old, old_key, new, new_key),
+                paths: diff_paths(old, old_key, new, new_key),
+                bindings: diff_values(bindings(old, old_key), bindings(new, new_key)),
+            };
+            if edit.rule.is_some() || !edit.paths.is_empty() || !edit.bindings.is_empty() {
+                edges.push((Some(old_key), Some(new_key), edit));
+            }
+        }
+        let added = matches.added.into_iter();
+        let added = added.map(|x| (None, Some(x), edge_outputs(new, x), DiffKind::Added));
+        let removed = matches.removed.into_iter();
+        let removed = removed.map(|x| (Some(x), None, edge_outputs(old, x), DiffKind::Removed));
+        for (old_key, new_key, outputs, kind) in added.chain(removed) {
+            let edit = EdgeEdit {
+                kind,
+                outputs,
+                rule: None,
+                paths: Vec::new(),
+                bindings: Vec::new(),
+            };
+            edges.push((old_key, new_key, edit));
+        }
+
+        Ok(Edits {
+            rules: diff.rules,
+            edges,
+            variables: diff.variables,
+        })
+    }
+}
+
+/// Replays the changes of the patched manifest onto a change list of the
+/// target manifest.
+struct Replay<'a, 'x> {
+    original: &'a Ninja,
+    patched: &'a Ninja,
+    target: &'x Ninja,
+    changes: &'a mut ChangeList<'x>,
+}
+
+/// One item of the patched manifest to replay.
+enum Step<'a> {
+    Variable(&'a VariableDiff),
+    Rule(&'a RuleDiff),
+    Edge(Option<EdgeKey>, Option<EdgeKey>, &'a EdgeEdit),
+}
+impl Step<'_> {
+    fn conflict(&self, error: Option<Diagnostic>) -> MergeConflict {
+        let (item, scope, name) = match self {
+            Step::Variable(x) => (MergeItem::Variable, x.scope.clone(), x.value.name.clone()),
+            Step::Rule(x) => (MergeItem::Rule, x.scope.clone(), x.name.clone()),
+            Step::Edge(_, _, x) => (MergeItem::Edge, None, x.outputs.join(" ")),
+        };
+        MergeConflict {
+            item,
+            scope,
+            name,
+            error,
+        }
+    }
+}
+
+impl<'x> ChangeList<'x> {
+    /// Replays onto this manifest the changes made from `original` to
+    /// `patched`, where this manifest is a regenerated version of
+    /// `original`: rules, `build` statements and top level variables that
+    /// were added, removed or changed.
+    ///
+    /// Rules and variables are matched like [`Ninja::diff`] does. The edits
+    /// of a `build` statement are its rule, its paths and its own bindings;
+    /// changes to the rules and variables it uses are replayed on their own.
+    /// When this manifest also changed the same item, the patch isn't
+    /// replayed and a conflict is returned instead, unless both made the
+    /// same change. So is an edit the manifest doesn't load with.
+    ///
+    /// Added rules and variables go right before the first statement using
+    /// them, or to the end of the file of their scope, like added `build`
+    /// statements.
+    pub fn merge(
+        &mut self,
+        original: &Ninja,
+        patched: &Ninja,
+    ) -> Result<Vec<MergeConflict>, EscapeError> {
+        let target = self.ninja();
+        let ours = Edits::new(original, patched)?;
+        let theirs = Edits::new(original, target)?;
+        let mut steps = Vec::new();
+        let mut conflicts = Vec::new();
+
+        for variable in &ours.variables {
+            let other = theirs
+                .variables
+                .iter()
+                .find(|x| (&x.scope, &x.value.name) == (&variable.scope, &variable.value.name));
+            match other {
+                Some(other) if other == variable => {}
+                Some(_) => conflicts.push(Step::Variable(variable).conflict(None)),
+                None => steps.push(Step::Variable(variable)),
+            }
+        }
+        for rule in &ours.rules {
+            let other = theirs
+                .rules
+                .iter()
+                .find(|x| (&x.scope, &x.name) == (&rule.scope, &rule.name));
+            match other {
+                Some(other) if other == rule => {}
+                Some(_) => conflicts.push(Step::Rule(rule).conflict(None)),
+                None => steps.push(Step::Rule(rule)),
+            }
+        }
+        for (original_key, patched_key, edit) in &ours.edges {
+            let step = Step::Edge(*original_key, *patched_key, edit);
+            let overlapping: Vec<_> = theirs
+                .edges
+                .iter()
+                .map(|x| &x.2)
+                .filter(|x| x.outputs.iter().any(|x| edit.outputs.contains(x)))
+                .collect();
+            if overlapping.is_empty() {
+                steps.push(step);
+            } else if overlapping != [edit] {
+                conflicts.push(step.conflict(None));
+            }
+        }
+
+        let start = self.clone();
+        let mut replay = Replay {
+            original,
+            patched,
+            target,
+            changes: self,
+        };
+        for step in &steps {
+            replay.step(step)?;
+        }
+        // Replays again one step at a time to find the edits breaking it,
+        // unless it was already broken.
+        if self.apply().is_err() && start.apply().is_ok() {
+            *self = start;
+            for step in &steps {
+                let before = self.clone();
+                let mut replay = Replay {
+                    original,
+                    patched,
+                    target,
+                    changes: self,
+                };
+                replay.step(step)?;
+                if let Err(e) = self.apply() {
+                    *self = before;
+                    conflicts.push(step.conflict(Some(e)));
+                }
+            }
+        }
+        Ok(conflicts)
+    }
+}
+
+impl Replay<'_, '_> {
+    fn step(&mut self, step: &Step) -> Result<(), EscapeError> {
+        match *step {
+            Step::Variable(variable) => self.variable(variable),
+            Step::Rule(rule) => self.rule(rule),
+            Step::Edge(original_key, patched_key, edit) => {
+                self.edge(original_key, patched_key, edit)
+            }
+        }
+    }
+
+    fn rule(&mut self, rule: &RuleDiff) -> Result<(), EscapeError> {
+        let (patched, target) = (&self.patched.data, &self.target.data);
+        let patched_key =
+            scope_key(patched, &rule.scope).and_then(|scope| patched.rule(scope, &rule.name));
+        let target_key =
+            scope_key(target, &rule.scope).and_then(|scope| target.rule(scope, &rule.name));
+        match (rule.kind, patched_key, target_key) {
+            (DiffKind::Added, Some(key), _) => {
+                let mut text = String::new();
+                write_rule(&mut text, &rule.name, &patched.rules[key])?;
+                let users: Vec<_> = patched
+                    .edges
+                    .iter()
+                    .filter(|(_, x)| x.rule == key)
+                    .filter_map(|(x, _)| self.target_edge(x))
+                    .collect();
+                self.insert(&rule.scope, &users, text);
+            }
+            (DiffKind::Removed, _, Some(key)) => self.changes.remove_rule(key),
+            (DiffKind::Changed, Some(patched_key), Some(key)) => {
+                for diff in &rule.bindings {
+                    match patched.rules[patched_key].binding(&diff.name) {
+                        Some(value) => self.changes.set_rule_binding(key, &diff.name, value)?,
+                        None => self.changes.remove_rule_binding(key, &diff.name),
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn variable(&mut self, variable: &VariableDiff) -> Result<(), EscapeError> {
+        let (patched, target) = (&self.patched.data, &self.target.data);
+        let name = &variable.value.name;
+        let (Some(patched_scope), Some(scope)) = (
+            scope_key(patched, &variable.scope),
+            scope_key(target, &variable.scope),
+        ) else {
+            return Ok(());
+        };
+        match patched.scopes[patched_scope].var(name) {
+            Some(var) if target.scopes[scope].var(name).is_none() => {
+                let text = format!("{} = {}\n", name, value_text(self.patched, var)?);
+                let users = self.variable_users(patched_scope, name)?;
+                self.insert(&variable.scope, &users, text);
+            }
+            Some(var) => {
+                let text = value_text(self.patched, var)?;
+                self.changes.set_variable_text(scope, name, &text);
+            }
+            None => self.changes.remove_variable(scope, name),
+        }
+        Ok(())
+    }
+
+    fn edge(
+        &mut self,
+        original_key: Option<EdgeKey>,
+        patched_key: Option<EdgeKey>,
+        edit: &EdgeEdit,
+    ) -> Result<(), EscapeError> {
+        let (patched, target) = (&self.patched.data, &self.target.data);
+        if edit.kind == DiffKind::Added {
+            let edge = &patched.edges[patched_key.unwrap()];
+            let rule = patched.rules[edge.rule].name.elem;
+            let mut text = String::new();
+            write_edge(&mut text, patched, edge, rule, &[])?;
+            self.append(&scope_name(patched, edge.scope), text);
+            return Ok(());
+        }
+        let Some(key) = edge_outputs(&self.original.data, original_key.unwrap())
+            .first()
+            .and_then(|x| target.node(x))
+            .and_then(|x| target.nodes[x].in_edge)
+        else {
+            return Ok(());
+        };
+        if edit.kind == DiffKind::Removed {
+            self.changes.remove_edge(key);
+            return Ok(());
+        }
+
+        if let Some(rule) = &edit.rule {
+            let name = rule.new.clone().unwrap_or_default();
+            self.changes.replace(target.edges[key].rule_loc, name);
+        }
+        for path in &edit.paths {
+            if path.added {
+                self.changes.add_edge_path(key, path.kind, &path.path)?;
+            } else if let Some(node) = target.node(&path.path) {
+                self.changes.remove_edge_path(key, path.kind, node);
+            }
+        }
+        let patched_edge = &patched.edges[patched_key.unwrap()];
+        for diff in &edit.bindings {
+            let var = patched_edge
+                .bindings
+                .iter()
+                .rev()
+                .find(|x| x.name.elem == diff.name);
+            match var {
+                Some(var) => {
+                    let text = value_text(self.patched, var)?;
+                    self.changes.set_edge_binding_text(key, &diff.name, &text);
+                }
+                None => self.changes.remove_edge_binding(key, &diff.name),
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the target has the `build` statement of the outputs of `edge`
+    /// of the patched manifest.
+    fn target_edge(&self, edge: EdgeKey) -> Option<Location> {
+        let target = &self.target.data;
+        let outputs = edge_outputs(&self.patched.data, edge);
+        let node = target.node(outputs.first()?)?;
+        let loc = target.edges[target.nodes[node].in_edge?].loc;
+        (loc != LOC_INVALID).then_some(loc)
+    }
+
+    /// Where the target has the statements of the patched manifest
+    /// evaluating the top level variable `name` of `scope`: `build`
+    /// statements and other variables.
+    fn variable_users(&self, scope: ScopeKey, name: &str) -> Result<Vec<Location>, EscapeError> {
+        let (patched, target) = (&self.patched.data, &self.target.data);
+        let sm = &self.patched.sm;
+        let mut users = Vec::new();
+        for (key, edge) in &patched.edges {
+            if !patched.scope_chain(edge.scope).any(|x| x == scope) {
+                continue;
+            }
+            let paths = edge
+                .all_paths()
+                .filter(|(_, x)| x.loc() != LOC_INVALID)
+                .map(|(_, x)| Ok(sm.get(x.loc().source_id).str_loc(x.loc()).to_string()));
+            let bindings = edge.bindings.iter().map(|x| value_text(self.patched, x));
+            for text in paths.chain(bindings) {
+                if uses_variable(&text?, name) {
+                    users.extend(self.target_edge(key));
+                    break;
+                }
+            }
+        }
+        for (other_scope, vars) in &patched.scopes {
+            if !patched.scope_chain(other_scope).any(|x| x == scope) {
+                continue;
+            }
+            let target_scope = scope_key(target, &scope_name(patched, other_scope));
+            for var in &vars.vars {
+                if var.name.elem == name || !uses_variable(&value_text(self.patched, var)?, name) {
+                    continue;
+                }
+                let target_var = target_scope.and_then(|x| target.scopes[x].var(&var.name.elem));
+                users.extend(
+                    target_var
+                        .map(|x| x.name.loc())
+                        .filter(|&x| x != LOC_INVALID),
+                );
+            }
+        }
+        Ok(users)
+    }
+
+    /// Adds `text` to the file of the scope `scope` of the target, before
+    /// the line of the first of `users`, or of the `include` or `subninja`
+    /// statement of the file it's in, or at the end.
+    fn insert(&mut self, scope: &Option<String>, users: &[Location], text: String) {
+        let target = self.target;
+        let Some(file) = scope_key(&target.data, scope).and_then(|x| scope_file(&target.data, x))
+        else {
+            return;
+        };
+        let Some(source) = file_source(target, file) else {
+            return;
+        };
+        let before = users
+            .iter()
+            .filter_map(|&loc| {
+                let mut loc = loc;
+                // Up the `include` and `subninja` statements to the file.
+                while loc.source_id != source {
+                    let (_, child) = target
+                        .data
+                        .files
+                        .iter()
+                        .find(|&(key, _)| file_source(target, key) == Some(loc.source_id))?;
+                    child.parent?;
+                    loc = child.path.loc();
+                    if loc == LOC_INVALID {
+                        return None;
+                    }
+                }
+                Some(loc.start)
+            })
+            .min();
+        match before {
+            Some(start) => {
+                let text_before = &target.sm.get(source).text()[..start];
+                let start = text_before.rfind('\n').map_or(0, |x| x + 1);
+                let loc = Location {
+                    start,
+                    stop: start,
+                    source_id: source,
+                };
+                self.changes.replace(loc, text);
+            }
+            None => self.changes.append(file, text),
+        }
+    }
+
+    /// Adds `text` at the end of the file of the scope `scope` of the target.
+    fn append(&mut self, scope: &Option<String>, text: String) {
+        let target = &self.target.data;
+        if let Some(file) = scope_key(target, scope).and_then(|x| scope_file(target, x)) {
+            self.changes.append(file, text);
+        }
+    }
+}
+
+/// Whether the value or path `text`, as it's written, evaluates the
+/// variable `name`.
+fn uses_variable(text: &str, name: &str) -> bool {
+    EvalString::parse_value(text).is_ok_and(|x| x.variables().any(|x| x == name))
+}
+
+/// The text of the value of `var` as it's written, or escaped if a `Data`
+/// building function added it.
+fn value_text(ninja: &Ninja, var: &Variable) -> Result<String, EscapeError> {
+    let loc = var.value.loc();
+    if loc == LOC_INVALID {
+        return EvalString::literal(var.value.elem.as_str()).to_value();
+    }
+    let loc = trim_line_break(ninja, loc);
+    Ok(ninja.sm.get(loc.source_id).str_loc(loc).to_string())
+}
+
+/// The scope of the `subninja` file `name`, or the root scope.
+fn scope_key(data: &Data, name: &Option<String>) -> Option<ScopeKey> {
+    match name {
+        Some(name) => data
+            .files
+            .values()
+            .find(|x| x.kind == FileKind::Subninja && &x.path.elem == name)
+            .map(|x| x.scope),
+        None => Some(data.root_scope()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs_err as fs;
+
+    const ORIGINAL: &str = "\
+cflags = -O2
+
+rule cc
+  command = cc $cflags -c $in -o $out
+
+rule asm
+  command = as $in -o $out
+
+build a.o: cc a.c
+build b.o: cc b.c
+build c.o: cc c.c
+";
+
+    // Local edits made to the generated manifest.
+    const PATCHED: &str = "\
+cflags = -O3
+
+rule cc
+  command = cc $cflags -c $in -o $out
+
+rule asm
+  command = as $in -o $out
+
+rule link
+  command = ld $in -o $out
+
+build a.o: cc a.c
+  cflags = -g
+build b.o: cc b.c | b.h
+build c.o: cc c.c
+build d.o: cc d.c
+build s.o: asm s.S
+build app: link a.o b.o
+";
+
+    // The generator's new output for the original configuration.
+    const REGENERATED: &str = "\
+cflags = -O2
+
+rule cc
+  command = cc $cflags -c $in -o $out
+
+build a.o: cc a.c
+  cflags = -O0
+build b.o: cc b.c | b.h
+build c.o: cc c.c
+build d.o: cc d.c
+";
+
+    fn load(dir: &std::path::Path, name: &str, text: &str) -> Ninja {
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        Ninja::load(path)
+    }
+
+    #[test]
+    fn merge_fixture() {
+        let dir = std::env::temp_dir().join(format!("ninja_editor_merge_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let original = load(&dir, "original.ninja", ORIGINAL);
+        let patched = load(&dir, "patched.ninja", PATCHED);
+        let regenerated = load(&dir, "regenerated.ninja", REGENERATED);
+
+        let mut changes = regenerated.change();
+        let conflicts = changes.merge(&original, &patched).unwrap();
+        let merged = changes.apply().unwrap();
+
+        // The variable change is replayed, the changes both sides made to
+        // `b.o` and `d.o` aren't duplicated, and the added rule goes right
+        // before its first user.
+        assert_eq!(
+            merged.sm.sources[0].text(),
+            "\
+cflags = -O3
+
+rule cc
+  command = cc $cflags -c $in -o $out
+
+build a.o: cc a.c
+  cflags = -O0
+build b.o: cc b.c | b.h
+build c.o: cc c.c
+build d.o: cc d.c
+rule link
+  command = ld $in -o $out
+build app: link a.o b.o
+"
+        );
+
+        let [edited, broken] = &conflicts[..] else {
+            panic!("{conflicts:?}");
+        };
+        // Both sides gave `a.o` a different binding.
+        assert_eq!(
+            (edited.item, edited.name.as_str(), &edited.error),
+            (MergeItem::Edge, "a.o", &None)
+        );
+        assert_eq!(edited.to_string(), "both sides changed build `a.o`");
+        // The regenerated manifest dropped the rule `s.o` uses.
+        assert_eq!(
+            (broken.item, broken.name.as_str()),
+            (MergeItem::Edge, "s.o")
+        );
+        let error = broken.error.as_ref().unwrap().to_string();
+        assert!(error.ends_with("unknown rule `asm`"), "{error}");
+    }
+}
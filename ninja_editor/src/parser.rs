@@ -1,36 +1,67 @@
 use crate::{
-    lexer::{Lexer, TokenKind},
-    Data, Edge, Rule, Source, SourceManager, L,
+    diagnostic::{Diagnostic, ParseError},
+    eval::EvalString,
+    lexer::{Lexer, Location, TokenKind, LOC_INVALID},
+    Binding, Data, DefaultStatement, DupBuild, Edge, FileKey, FileKind, LoadOptions, NodeKey,
+    PathKind, Pool, Rule, ScopeKey, Source, SourceManager, Variable, L,
 };
 use std::path::Path;
 
 type K = TokenKind;
 
+/// The variables a rule may set.
+pub(crate) const RULE_VARS: [&str; 11] = [
+    "command",
+    "depfile",
+    "dyndep",
+    "deps",
+    "description",
+    "generator",
+    "msvc_deps_prefix",
+    "pool",
+    "restat",
+    "rspfile",
+    "rspfile_content",
+];
+
 struct Parser<'x> {
     lexer: Lexer<'x>,
     source: &'x Source,
+    file: FileKey,
+    options: LoadOptions,
 }
 
 macro_rules! expect {
     ($obj:expr, $kind:ident) => {{
-        let next = $obj.lexer.next();
+        let next = $obj.lexer.next()?;
         if next.kind != K::$kind {
-            panic!("Expected {}, got {:?}", stringify!($kind), next.kind);
+            return Err(ParseError::new(
+                format!("expected {}, got {:?}", stringify!($kind), next.kind),
+                next.loc,
+            ));
         }
         next
     }};
 }
 
-fn parse_let(parser: &mut Parser) -> (String, L<String>) {
-    let key_token = parser.lexer.read_ident();
-    let key = parser.source.str_loc(key_token).to_string();
+fn evaluate(data: &Data, scope: ScopeKey, value: &EvalString) -> String {
+    value.evaluate(|name| {
+        data.var(scope, name)
+            .map(|x| x.value.elem.clone())
+            .unwrap_or_default()
+    })
+}
+
+fn parse_let(parser: &mut Parser) -> Result<(L<String>, L<EvalString>), ParseError> {
+    let key_token = parser.lexer.read_ident()?;
+    let key = L::new(parser.source.str_loc(key_token).to_string(), key_token);
     expect!(parser, Equals);
-    let value = parser.lexer.read_var_value();
+    let value = parser.lexer.read_var_value()?;
 
-    (key, value)
+    Ok((key, value))
 }
 
-fn parse_rule<'x>(parser: &mut Parser<'x>, data: &mut Data<'x>) {
+fn parse_rule<'x>(parser: &mut Parser<'x>, data: &mut Data<'x>) -> Result<(), ParseError> {
     let name_token = expect!(parser, Ident);
     let name = parser.source.str(&name_token);
     let name = L {
@@ -39,69 +70,115 @@ fn parse_rule<'x>(parser: &mut Parser<'x>, data: &mut Data<'x>) {
     };
     expect!(parser, Newline);
 
+    let mut bindings = Vec::new();
+
+    while let K::Indent = parser.lexer.peek()?.kind {
+        parser.lexer.next()?;
+
+        let (key, value) = parse_let(parser)?;
+        if !RULE_VARS.contains(&key.elem.as_str()) {
+            return Err(ParseError::new(
+                format!("unexpected variable `{}`", key.elem),
+                key.loc,
+            ));
+        }
+        bindings.push(Binding { name: key, value });
+    }
+
     let rule = Rule {
         name,
-        ..Default::default()
+        bindings,
+        file: Some(parser.file),
     };
-    let mut has_command = false;
+    if rule.binding("command").is_none() {
+        return Err(ParseError::new("expected `command =` line", name.loc));
+    }
+    if rule.binding("rspfile").is_some() != rule.binding("rspfile_content").is_some() {
+        return Err(ParseError::new(
+            "rspfile and rspfile_content need to be both specified",
+            name.loc,
+        ));
+    }
 
-    while let K::Indent = parser.lexer.peek().kind {
-        parser.lexer.next();
+    match data.insert_rule(rule) {
+        Some(_) => Ok(()),
+        None => Err(ParseError::new(
+            format!("duplicate rule `{}`", name.elem),
+            name.loc,
+        )),
+    }
+}
 
-        let (key, _value) = parse_let(parser);
+fn parse_pool(parser: &mut Parser<'_>, data: &mut Data) -> Result<(), ParseError> {
+    let name_token = expect!(parser, Ident);
+    let name = L::new(parser.source.str(&name_token).to_string(), name_token.loc);
+    expect!(parser, Newline);
 
-        match key.as_str() {
-            "command" => {
-                // rule.command = value;
-                has_command = true;
-            }
-            "depfile" => {
-                // rule.depfile = Some(value)
-            }
-            "deps" => {
-                // rule.deps = Some(value),
-            }
-            "description" => {
-                // rule.description = Some(value),
-            }
-            "restat" => {
-                // rule.restat = Some(value),
-            }
-            "generator" => {
-                // rule.generator = Some(value),
-            }
-            _ => todo!("unknown key `{key}`"),
+    let scope = data.files[parser.file].scope;
+    let mut depth = None;
+
+    while let K::Indent = parser.lexer.peek()?.kind {
+        parser.lexer.next()?;
+
+        let (key, value) = parse_let(parser)?;
+        if key.elem != "depth" {
+            return Err(ParseError::new(
+                format!("unexpected variable `{}`", key.elem),
+                key.loc,
+            ));
         }
+        let Ok(n) = evaluate(data, scope, &value.elem).parse() else {
+            return Err(ParseError::new("invalid pool depth", value.loc));
+        };
+        depth = Some(L::new(n, value.loc));
     }
 
-    assert!(has_command);
-
-    assert!(!data.rules_by_name.contains_key(name.elem));
-    let rule = data.rules.insert(rule);
-    data.rules_by_name.insert(name.elem, rule);
+    let Some(depth) = depth else {
+        return Err(ParseError::new("expected `depth =` line", name.loc));
+    };
+    let pool = Pool {
+        name: name.clone(),
+        depth,
+        file: Some(parser.file),
+    };
+    match data.insert_pool(pool) {
+        Some(_) => Ok(()),
+        None => Err(ParseError::new(
+            format!("duplicate pool `{}`", name.elem),
+            name.loc,
+        )),
+    }
 }
 
-fn parse_build(parser: &mut Parser<'_>, data: &mut Data) {
-    let mut ins = Vec::new();
-    let mut outs = Vec::new();
-
+fn read_paths(
+    parser: &mut Parser<'_>,
+    paths: &mut Vec<(PathKind, L<EvalString>)>,
+    kind: PathKind,
+) -> Result<(), ParseError> {
     loop {
-        let tmp = parser.lexer.read_path();
+        let tmp = parser.lexer.read_path()?;
         if tmp.elem.is_empty() {
-            break;
+            return Ok(());
         }
-
-        outs.push(tmp);
+        paths.push((kind, tmp));
     }
+}
 
-    if parser.lexer.maybe_peek(K::Pipe) {
-        loop {
-            let tmp = parser.lexer.read_path();
-            if tmp.elem.is_empty() {
-                break;
-            }
-            // TODO: ignore for now
-        }
+fn parse_build(
+    parser: &mut Parser<'_>,
+    data: &mut Data,
+    sm: &SourceManager,
+    warnings: &mut Vec<Diagnostic>,
+    build_loc: Location,
+) -> Result<(), ParseError> {
+    let mut paths = Vec::new();
+
+    read_paths(parser, &mut paths, PathKind::Out)?;
+    if paths.is_empty() {
+        return Err(ParseError::new("expected path", build_loc));
+    }
+    if parser.lexer.maybe_peek(K::Pipe)? {
+        read_paths(parser, &mut paths, PathKind::ImplicitOut)?;
     }
 
     expect!(parser, Colon);
@@ -109,113 +186,420 @@ fn parse_build(parser: &mut Parser<'_>, data: &mut Data) {
     let rule_name_token = expect!(parser, Ident);
     let rule_name = parser.source.str(&rule_name_token);
 
-    let Some(&rule) = data.rules_by_name.get(rule_name) else {
-        panic!("unknown rule `{}`", rule_name);
+    let scope = data.files[parser.file].scope;
+    let Some(rule) = data.rule(scope, rule_name) else {
+        return Err(ParseError::new(
+            format!("unknown rule `{}`", rule_name),
+            rule_name_token.loc,
+        ));
     };
 
-    loop {
-        let tmp = parser.lexer.read_path();
-        if tmp.elem.is_empty() {
-            break;
-        }
+    read_paths(parser, &mut paths, PathKind::In)?;
+    if parser.lexer.maybe_peek(K::Pipe)? {
+        read_paths(parser, &mut paths, PathKind::ImplicitIn)?;
+    }
+    if parser.lexer.maybe_peek(K::Pipe2)? {
+        read_paths(parser, &mut paths, PathKind::OrderOnlyIn)?;
+    }
+    if parser.lexer.maybe_peek(K::PipeAt)? {
+        read_paths(parser, &mut paths, PathKind::Validation)?;
     }
 
-    if parser.lexer.maybe_peek(K::Pipe) {
-        // Add all implicit deps
-        loop {
-            let tmp = parser.lexer.read_path();
-            if tmp.elem.is_empty() {
-                break;
-            }
+    expect!(parser, Newline);
 
-            ins.push(tmp);
+    let mut edge = Edge::new(rule, rule_name_token.loc, build_loc, parser.file, scope);
+    while parser.lexer.peek()?.kind == K::Indent {
+        parser.lexer.next()?;
+
+        // Bindings are evaluated in the scope of the file, paths also see
+        // the bindings.
+        let (key, value) = parse_let(parser)?;
+        let value = L::new(evaluate(data, scope, &value.elem), value.loc);
+        edge.bindings.push(Variable {
+            name: key,
+            value,
+            file: parser.file,
+        });
+    }
+    let evaluated: Vec<_> = paths
+        .into_iter()
+        .map(|(kind, path)| {
+            let evaluated = path.elem.evaluate(|name| {
+                edge.binding(name)
+                    .or_else(|| data.var(scope, name).map(|x| x.value.elem.as_str()))
+                    .unwrap_or_default()
+                    .to_string()
+            });
+            (kind, L::new(evaluated, path.loc))
+        })
+        .collect();
+    let (outputs, inputs): (Vec<_>, Vec<_>) = evaluated
+        .into_iter()
+        .partition(|(kind, _)| kind.is_output());
+
+    // Decided before adding the edge, which isn't added without outputs.
+    let mut kept: Vec<(PathKind, L<String>)> = Vec::new();
+    for (kind, path) in outputs {
+        let first = match kept.iter().find(|(_, x)| x.elem == path.elem) {
+            Some((_, x)) => Some(Some(x.loc)),
+            None => data
+                .node(&path.elem)
+                .and_then(|x| first_output_loc(data, x)),
+        };
+        match (first, parser.options.dupbuild) {
+            (Some(first), DupBuild::Err) => {
+                return Err(ParseError::new(
+                    duplicate_output_message(sm, &path.elem, first),
+                    path.loc,
+                ));
+            }
+            (Some(first), DupBuild::Warn) => {
+                let message = format!(
+                    "{}; builds involving it will not be correct",
+                    duplicate_output_message(sm, &path.elem, first)
+                );
+                warnings.push(Diagnostic::at(sm, path.loc, message));
+                // Still a place where the path is written.
+                data.insert_node(path);
+            }
+            _ => kept.push((kind, path)),
         }
     }
+    if kept.is_empty() {
+        for (_, path) in inputs {
+            data.insert_node(path);
+        }
+        return Ok(());
+    }
+    let edge = data.edges.insert(edge);
+    for (kind, path) in kept.into_iter().chain(inputs) {
+        data.insert_edge_path(edge, kind, path);
+    }
 
-    if parser.lexer.maybe_peek(K::Pipe2) {
-        // Add all order-only deps
-        loop {
-            let tmp = parser.lexer.read_path();
-            if tmp.elem.is_empty() {
-                break;
-            }
+    let dyndep = data.evaluate_edge_unescaped(edge, "dyndep");
+    if !dyndep.is_empty() && data.dyndep_file(edge).is_none() {
+        return Err(ParseError::new(
+            format!("dyndep `{}` is not an input", dyndep),
+            build_loc,
+        ));
+    }
 
-            ins.push(tmp);
-        }
+    Ok(())
+}
+
+/// Where the edge generating `node` writes it: `None` if nothing generates
+/// it, `Some(None)` if the path isn't written anywhere.
+fn first_output_loc(data: &Data, node: NodeKey) -> Option<Option<Location>> {
+    let edge = data.nodes[node].in_edge?;
+    let loc = data.edges[edge]
+        .all_paths()
+        .find(|(kind, x)| kind.is_output() && x.elem == node)
+        .map(|(_, x)| x.loc())
+        .filter(|&x| x != LOC_INVALID);
+    Some(loc)
+}
+
+/// Says that `path` already has a producer, and where.
+fn duplicate_output_message(sm: &SourceManager, path: &str, first: Option<Location>) -> String {
+    let mut message = format!("multiple rules generate `{}`", path);
+    if let Some(loc) = first {
+        let first = Diagnostic::at(sm, loc, "");
+        message += &format!(
+            ", first at {}:{}:{}",
+            first.path.display(),
+            first.line,
+            first.column
+        );
     }
+    message
+}
 
-    expect!(parser, Newline);
+fn parse_var(parser: &mut Parser<'_>, data: &mut Data) -> Result<(), ParseError> {
+    let (key, value) = parse_let(parser)?;
+    let scope = data.files[parser.file].scope;
+    let value = L::new(evaluate(data, scope, &value.elem), value.loc);
+    data.insert_var(parser.file, key, value);
+
+    Ok(())
+}
 
-    while parser.lexer.peek().kind == K::Indent {
-        parser.lexer.next();
+fn parse_default(parser: &mut Parser<'_>, data: &mut Data) -> Result<(), ParseError> {
+    let scope = data.files[parser.file].scope;
+    let mut targets = Vec::new();
 
-        // args
-        let _ = parse_let(parser);
+    loop {
+        let path = parser.lexer.read_path()?;
+        if path.elem.is_empty() {
+            break;
+        }
+        let evaluated = evaluate(data, scope, &path.elem);
+        let node = data.insert_node(L::new(evaluated, path.loc));
+        targets.push(L::new(node, path.loc));
     }
 
-    for i in outs.iter().chain(ins.iter()) {
-        data.nodes.entry(i.elem.clone()).or_default().push(i.loc);
+    if targets.is_empty() {
+        let loc = Location {
+            start: parser.lexer.offset(),
+            stop: parser.lexer.offset(),
+            source_id: parser.source.id,
+        };
+        return Err(ParseError::new("expected target name", loc));
     }
-    let edge = Edge {
-        rule,
-        rule_loc: rule_name_token.loc,
+    data.defaults.push(DefaultStatement {
+        file: parser.file,
+        targets,
+    });
+
+    Ok(())
+}
+
+fn parse_include(
+    parser: &mut Parser<'_>,
+    data: &mut Data,
+    sm: &mut SourceManager,
+    warnings: &mut Vec<Diagnostic>,
+    kind: FileKind,
+) -> Result<(), ParseError> {
+    let path = parser.lexer.read_path()?;
+    let scope = data.files[parser.file].scope;
+    let evaluated = evaluate(data, scope, &path.elem);
+
+    let source = sm
+        .load(&evaluated)
+        .map_err(|e| ParseError::new(format!("loading `{}`: {}", evaluated, e), path.loc))?;
+    let file = data.insert_file(parser.file, L::new(evaluated, path.loc), kind);
+    let lexer = Lexer::new(&source.text, source.id);
+    let mut parser = Parser {
+        lexer,
+        source,
+        file,
+        options: parser.options,
     };
 
-    data.edges.insert(edge);
+    parse_item(&mut parser, data, sm, warnings)
 }
 
-fn parse_var(parser: &mut Parser<'_>, data: &mut Data) {
-    use std::collections::hash_map::Entry;
+fn parse_item<'x>(
+    parser: &mut Parser<'x>,
+    data: &mut Data<'x>,
+    sm: &mut SourceManager,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<(), ParseError> {
+    loop {
+        let first = parser.lexer.peek()?;
+        if first.kind != K::Ident {
+            parser.lexer.next()?;
+        }
+        match first.kind {
+            K::Eof => break,
+            K::Newline => continue,
+            K::Rule => parse_rule(parser, data)?,
+            K::Pool => parse_pool(parser, data)?,
+            K::Build => parse_build(parser, data, sm, warnings, first.loc)?,
+            K::Default => parse_default(parser, data)?,
+            K::Ident => parse_var(parser, data)?,
+            K::Include => parse_include(parser, data, sm, warnings, FileKind::Include)?,
+            K::Subninja => parse_include(parser, data, sm, warnings, FileKind::Subninja)?,
+            _ => {
+                return Err(ParseError::new(
+                    format!("unexpected {:?}", first.kind),
+                    first.loc,
+                ))
+            }
+        };
+    }
+
+    Ok(())
+}
+
+pub fn parse(
+    sm: &mut SourceManager,
+    data: &mut Data,
+    path: &Path,
+    options: LoadOptions,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<(), Diagnostic> {
+    let source = sm
+        .load(path)
+        .map_err(|e| Diagnostic::for_file(path, e.to_string()))?;
+    let file = data.root_file();
+    data.files[file].path = L::new(path.to_string_lossy().into_owned(), LOC_INVALID);
 
-    let (key, value) = parse_let(parser);
-    match data.vars.entry(key) {
-        Entry::Occupied(_) => panic!("var already used"),
-        Entry::Vacant(x) => x.insert(value),
+    let lexer = Lexer::new(source.text_parser(), source.id);
+    let mut parser = Parser {
+        lexer,
+        source,
+        file,
+        options,
     };
+
+    parse_item(&mut parser, data, sm, warnings).map_err(|e| Diagnostic::from_parse_error(sm, e))
 }
 
-fn parse_default(parser: &mut Parser<'_>, data: &mut Data) {
-    let s = parser.lexer.read_path();
+/// A `build` statement of a dyndep file, with evaluated paths.
+pub(crate) struct DyndepStatement {
+    pub(crate) output: L<String>,
+    pub(crate) implicit_outs: Vec<L<String>>,
+    pub(crate) implicit_ins: Vec<L<String>>,
+    pub(crate) restat: bool,
+}
 
-    match data.default {
-        Some(_) => panic!("default edge already defined"),
-        None => data.default = Some(s),
+struct DyndepParser<'x> {
+    lexer: Lexer<'x>,
+    text: &'x str,
+    version: Option<String>,
+}
+impl DyndepParser<'_> {
+    /// Evaluates in the scope of the dyndep file, which only has the version.
+    fn evaluate(&self, value: &EvalString) -> String {
+        value.evaluate(|name| match name {
+            "ninja_dyndep_version" => self.version.clone().unwrap_or_default(),
+            _ => String::new(),
+        })
+    }
+    fn parse_let(&mut self) -> Result<(L<String>, L<EvalString>), ParseError> {
+        let key_token = self.lexer.read_ident()?;
+        let key = L::new(
+            self.text[key_token.start..key_token.stop].to_string(),
+            key_token,
+        );
+        expect!(self, Equals);
+        let value = self.lexer.read_var_value()?;
+        Ok((key, value))
+    }
+    fn read_paths(&mut self, paths: &mut Vec<L<String>>) -> Result<(), ParseError> {
+        loop {
+            let path = self.lexer.read_path()?;
+            if path.elem.is_empty() {
+                return Ok(());
+            }
+            paths.push(L::new(self.evaluate(&path.elem), path.loc));
+        }
     }
 }
 
-fn parse_include(parser: &mut Parser<'_>, data: &mut Data, sm: &mut SourceManager) {
-    let path = parser.lexer.read_path();
-
-    let source = sm.load(path.elem);
-    let lexer = Lexer::new(&source.text, source.id);
-    let mut parser = Parser { lexer, source };
+const EXPECTED_DYNDEP_VERSION: &str = "expected `ninja_dyndep_version = ...`";
 
-    parse_item(&mut parser, data, sm);
-}
+/// Parses a dyndep file, which `text` must be terminated by a zero like the
+/// text of a [`Source`].
+pub(crate) fn parse_dyndep(text: &str) -> Result<Vec<DyndepStatement>, ParseError> {
+    let mut parser = DyndepParser {
+        lexer: Lexer::new(text, LOC_INVALID.source_id),
+        text,
+        version: None,
+    };
+    let mut statements = Vec::new();
 
-fn parse_item<'x>(parser: &mut Parser<'x>, data: &mut Data<'x>, sm: &mut SourceManager) {
     loop {
-        let first = parser.lexer.peek();
+        let first = parser.lexer.peek()?;
         if first.kind != K::Ident {
-            parser.lexer.next();
+            parser.lexer.next()?;
         }
         match first.kind {
             K::Eof => break,
             K::Newline => continue,
-            K::Rule => parse_rule(parser, data),
-            K::Build => parse_build(parser, data),
-            K::Default => parse_default(parser, data),
-            K::Ident => parse_var(parser, data),
-            K::Include | K::Subninja => parse_include(parser, data, sm),
-            _ => todo!("{:?}", first.kind),
+            K::Ident if parser.version.is_none() => {
+                let (key, value) = parser.parse_let()?;
+                if key.elem != "ninja_dyndep_version" {
+                    return Err(ParseError::new(EXPECTED_DYNDEP_VERSION, key.loc));
+                }
+                let version = parser.evaluate(&value.elem);
+                let mut parts = version.splitn(2, '.');
+                let major = parts.next().unwrap_or_default();
+                let minor = parts.next().unwrap_or("0");
+                if major != "1" || minor.split('.').next() != Some("0") {
+                    return Err(ParseError::new(
+                        format!("unsupported `ninja_dyndep_version = {}`", version),
+                        value.loc,
+                    ));
+                }
+                parser.version = Some(version);
+            }
+            K::Build if parser.version.is_some() => {
+                statements.push(parse_dyndep_build(&mut parser, first.loc)?);
+            }
+            K::Build => return Err(ParseError::new(EXPECTED_DYNDEP_VERSION, first.loc)),
+            _ => {
+                return Err(ParseError::new(
+                    format!("unexpected {:?}", first.kind),
+                    first.loc,
+                ))
+            }
+        }
+    }
+
+    if parser.version.is_none() {
+        let loc = Location {
+            start: parser.lexer.offset(),
+            stop: parser.lexer.offset(),
+            source_id: LOC_INVALID.source_id,
         };
+        return Err(ParseError::new(EXPECTED_DYNDEP_VERSION, loc));
     }
+    Ok(statements)
 }
 
-pub fn parse(sm: &mut SourceManager, data: &mut Data, path: &Path) {
-    let source = sm.load(path);
-    let lexer = Lexer::new(source.text_parser(), source.id);
-    let mut parser = Parser { lexer, source };
+fn parse_dyndep_build(
+    parser: &mut DyndepParser,
+    build_loc: Location,
+) -> Result<DyndepStatement, ParseError> {
+    let mut outs = Vec::new();
+    parser.read_paths(&mut outs)?;
+    let output = match outs.len() {
+        0 => return Err(ParseError::new("expected path", build_loc)),
+        1 => outs.pop().unwrap(),
+        _ => {
+            return Err(ParseError::new(
+                "explicit outputs not supported",
+                outs[1].loc,
+            ))
+        }
+    };
+    let mut implicit_outs = Vec::new();
+    if parser.lexer.maybe_peek(K::Pipe)? {
+        parser.read_paths(&mut implicit_outs)?;
+    }
+
+    expect!(parser, Colon);
+    let rule = expect!(parser, Ident);
+    if &parser.text[rule.loc.start..rule.loc.stop] != "dyndep" {
+        return Err(ParseError::new(
+            "expected build command name `dyndep`",
+            rule.loc,
+        ));
+    }
+
+    let mut ins = Vec::new();
+    parser.read_paths(&mut ins)?;
+    if let Some(input) = ins.first() {
+        return Err(ParseError::new("explicit inputs not supported", input.loc));
+    }
+    let mut implicit_ins = Vec::new();
+    if parser.lexer.maybe_peek(K::Pipe)? {
+        parser.read_paths(&mut implicit_ins)?;
+    }
+    let next = parser.lexer.peek()?;
+    match next.kind {
+        K::Pipe2 => return Err(ParseError::new("order-only inputs not supported", next.loc)),
+        K::PipeAt => return Err(ParseError::new("validations not supported", next.loc)),
+        _ => {}
+    }
+    expect!(parser, Newline);
+
+    let mut restat = false;
+    while parser.lexer.peek()?.kind == K::Indent {
+        parser.lexer.next()?;
+        let (key, value) = parser.parse_let()?;
+        if key.elem != "restat" {
+            return Err(ParseError::new("binding is not `restat`", key.loc));
+        }
+        restat = !parser.evaluate(&value.elem).is_empty();
+    }
 
-    parse_item(&mut parser, data, sm);
+    Ok(DyndepStatement {
+        output,
+        implicit_outs,
+        implicit_ins,
+        restat,
+    })
 }
@@ -0,0 +1,272 @@
+use crate::{
+    cst::{ItemKind, SyntaxKind, SyntaxTree},
+    lexer::{Location, LOC_INVALID},
+    lint::references,
+    parser::RULE_VARS,
+    ChangeList, Data, Diagnostic, EdgeKey, NodeKey, Source,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+impl Data<'_> {
+    /// The edges ninja may run to build `targets`: [`Data::edges_for_targets`]
+    /// with order-only inputs, and the edges building the validations of
+    /// those edges.
+    pub fn needed_edges(&self, targets: &[NodeKey]) -> HashSet<EdgeKey> {
+        let mut edges = HashSet::new();
+        let mut validations = HashSet::new();
+        let mut pending = targets.to_vec();
+        while !pending.is_empty() {
+            let reached: Vec<_> = self
+                .edges_for_targets(&pending, true)
+                .filter(|&x| edges.insert(x))
+                .collect();
+            pending = reached
+                .iter()
+                .flat_map(|&x| &self.edges[x].validations)
+                .map(|x| x.elem)
+                .filter(|&x| validations.insert(x))
+                .collect();
+        }
+        edges
+    }
+}
+
+/// A top level statement of one of the manifest files.
+struct Item<'a> {
+    kind: ItemKind,
+    /// From its first byte to the first byte of the next item.
+    range: Range<usize>,
+    /// The name of a variable, or of a rule.
+    name: Option<&'a str>,
+    /// The variables it references.
+    references: Vec<String>,
+    removed: bool,
+}
+
+impl<'x> ChangeList<'x> {
+    /// Removes the `build` statements ninja doesn't need to build `targets`,
+    /// or the defaults without targets, keeping order-only inputs,
+    /// validations and the edges regenerating the manifest. Rules no kept
+    /// edge uses are removed too, as are the top level variables that only
+    /// removed statements referenced, and `default` targets that are no
+    /// longer in the graph. Comments right before removed statements go with
+    /// them. Returns how many edges were removed.
+    pub fn prune(&mut self, targets: &[NodeKey]) -> Result<usize, Diagnostic> {
+        let ninja = self.ninja();
+        let data = &ninja.data;
+        let targets = match targets.is_empty() {
+            true => data.default_nodes(),
+            false => targets.to_vec(),
+        };
+
+        // Both relative to the working directory, as when loading.
+        let manifests: HashSet<_> = ninja
+            .sm
+            .sources
+            .iter()
+            .filter_map(|x| std::path::absolute(&x.path).ok())
+            .collect();
+        let regenerating = data
+            .nodes
+            .iter()
+            .filter(|(_, x)| {
+                std::path::absolute(&x.path).is_ok_and(|path| manifests.contains(&path))
+            })
+            .map(|(key, _)| key);
+        let targets: Vec<_> = targets.into_iter().chain(regenerating).collect();
+        let kept = data.needed_edges(&targets);
+        let kept_nodes: HashSet<_> = kept
+            .iter()
+            .flat_map(|&x| data.edges[x].all_paths())
+            .map(|(_, x)| x.elem)
+            .collect();
+
+        let removed_edges: HashSet<_> = data
+            .edges
+            .iter()
+            .filter(|&(key, x)| !kept.contains(&key) && x.loc != LOC_INVALID)
+            .map(|(_, x)| x.loc)
+            .collect();
+        let kept_rules: HashSet<_> = kept.iter().map(|&x| data.edges[x].rule).collect();
+        let removed_rules: HashSet<_> = data
+            .rules
+            .iter()
+            .filter(|&(key, x)| !kept_rules.contains(&key) && x.file.is_some())
+            .map(|(_, x)| x.name.loc())
+            .collect();
+
+        let mut files = Vec::new();
+        for source in &ninja.sm.sources {
+            let tree = SyntaxTree::build(source.text(), source.id)
+                .map_err(|e| Diagnostic::from_parse_error(&ninja.sm, e))?;
+            files.push((*source, tree));
+        }
+        let mut items: Vec<Vec<Item>> = files
+            .iter()
+            .map(|(source, tree)| file_items(source, tree))
+            .collect();
+
+        let node_locs: HashMap<Location, NodeKey> = data
+            .nodes
+            .iter()
+            .flat_map(|(key, node)| node.locs.iter().map(move |&loc| (loc, key)))
+            .collect();
+        let mut default_changes = Vec::new();
+        for (n, (source, tree)) in files.iter().enumerate() {
+            for (item, syntax) in items[n].iter_mut().zip(tree.items()) {
+                let loc = |range: Range<usize>| Location {
+                    start: range.start,
+                    stop: range.end,
+                    source_id: source.id,
+                };
+                match item.kind {
+                    ItemKind::Build => {
+                        let keyword = syntax.first(SyntaxKind::Keyword).unwrap();
+                        item.removed = removed_edges.contains(&loc(keyword.range.clone()));
+                    }
+                    ItemKind::Rule => {
+                        let name = syntax.first(SyntaxKind::Ident).unwrap();
+                        item.removed = removed_rules.contains(&loc(name.range.clone()));
+                    }
+                    ItemKind::Default => {
+                        let paths: Vec<_> = syntax
+                            .tokens
+                            .iter()
+                            .filter(|x| x.kind == SyntaxKind::Path && !x.range.is_empty())
+                            .collect();
+                        let kept_paths: Vec<_> = paths
+                            .iter()
+                            .filter(|x| {
+                                let node = node_locs.get(&loc(x.range.clone()));
+                                node.is_some_and(|key| kept_nodes.contains(key))
+                            })
+                            .map(|x| tree.str(x))
+                            .collect();
+                        if kept_paths.is_empty() {
+                            item.removed = true;
+                        } else if kept_paths.len() < paths.len() {
+                            let text = format!("default {}\n", kept_paths.join(" "));
+                            default_changes.push((loc(syntax.range()), text));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Variables only referenced, directly or not, by removed statements.
+        let all = used_variables(&items, false);
+        let still = used_variables(&items, true);
+        for item in items.iter_mut().flatten() {
+            if let (ItemKind::Variable, Some(name)) = (item.kind, item.name) {
+                item.removed = all.contains(name)
+                    && !still.contains(name)
+                    && !RULE_VARS.contains(&name)
+                    && !matches!(name, "builddir" | "ninja_required_version");
+            }
+        }
+
+        for (n, (source, _)) in files.iter().enumerate() {
+            for range in removed_ranges(&items[n]) {
+                let loc = Location {
+                    start: range.start,
+                    stop: range.end,
+                    source_id: source.id,
+                };
+                self.replace(loc, String::new());
+            }
+        }
+        for (loc, text) in default_changes {
+            self.replace(loc, text);
+        }
+        Ok(removed_edges.len())
+    }
+}
+
+fn file_items<'a>(source: &Source, tree: &'a SyntaxTree) -> Vec<Item<'a>> {
+    let syntax = tree.items();
+    let starts: Vec<_> = syntax.iter().map(|x| x.range().start).collect();
+    syntax
+        .iter()
+        .enumerate()
+        .map(|(n, x)| {
+            let end = starts.get(n + 1).copied().unwrap_or(source.text().len());
+            let name = match x.kind {
+                ItemKind::Variable | ItemKind::Rule => {
+                    x.first(SyntaxKind::Ident).map(|x| tree.str(x))
+                }
+                _ => None,
+            };
+            let mut refs = Vec::new();
+            x.for_each_token(&mut |x| {
+                if matches!(x.kind, SyntaxKind::Path | SyntaxKind::Value) {
+                    refs.extend(references(tree.str(x)));
+                }
+            });
+            Item {
+                kind: x.kind,
+                range: starts[n]..end,
+                name,
+                references: refs,
+                removed: false,
+            }
+        })
+        .collect()
+}
+
+/// The names of the variables referenced by the statements, or only by the
+/// ones not removed, and by the variables they reference.
+fn used_variables(items: &[Vec<Item>], kept_only: bool) -> HashSet<String> {
+    let items: Vec<_> = items
+        .iter()
+        .flatten()
+        .filter(|x| !(kept_only && x.removed))
+        .collect();
+    let mut used: HashSet<_> = items
+        .iter()
+        .filter(|x| x.kind != ItemKind::Variable)
+        .flat_map(|x| x.references.iter().cloned())
+        .collect();
+    loop {
+        let count = used.len();
+        for item in &items {
+            if item.kind == ItemKind::Variable && item.name.is_some_and(|x| used.contains(x)) {
+                used.extend(item.references.iter().cloned());
+            }
+        }
+        if used.len() == count {
+            return used;
+        }
+    }
+}
+
+/// The text of the removed items of a file, with the comments right before
+/// them, and a blank line after them that would otherwise be doubled.
+fn removed_ranges(items: &[Item]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut n = 0;
+    while n < items.len() {
+        if !items[n].removed {
+            n += 1;
+            continue;
+        }
+        let mut start = n;
+        while start > 0 && items[start - 1].kind == ItemKind::Comment {
+            start -= 1;
+        }
+        let mut end = n + 1;
+        while end < items.len() && items[end].removed {
+            end += 1;
+        }
+        let blank_before = start == 0 || items[start - 1].kind == ItemKind::Blank;
+        if blank_before && items.get(end).is_some_and(|x| x.kind == ItemKind::Blank) {
+            end += 1;
+        }
+        ranges.push(items[start].range.start..items[end - 1].range.end);
+        n = end;
+    }
+    ranges
+}
@@ -0,0 +1,561 @@
+//! Declarative rewrites of a manifest, read from TOML.
+//!
+//! A recipe is a list of `[[rewrite]]` tables, each selecting items of the
+//! manifest and saying what to do with them:
+//!
+//! ```toml
+//! [[rewrite]]
+//! match = "node"
+//! glob = "cmake_*"
+//! action = "prefix"
+//! value = "p_"
+//!
+//! [[rewrite]]
+//! match = "edge"
+//! rule = "cc"
+//! action = "set"
+//! binding = "pool"
+//! value = "heavy"
+//! ```
+//!
+//! `match` is `node`, `rule`, `edge` or `variable`. The conditions, all
+//! optional, are:
+//!
+//! - `glob` and `regex`: on the path of a node, the name of a rule or
+//!   variable, or the paths of an edge, its outputs by default;
+//! - `rule`: the name of the rule of an edge, or of an edge the node is a
+//!   path of;
+//! - `kind`: for nodes, which kind of path of an edge the node has to be; for
+//!   edges, which paths `glob` and `regex` look at. One of `output`,
+//!   `implicit-output`, `input`, `implicit-input`, `order-only-input` and
+//!   `validation`.
+//!
+//! The actions are:
+//!
+//! | `action`         | on                  | parameters                         |
+//! |------------------|---------------------|------------------------------------|
+//! | `rename`         | nodes, rules        | `value`                            |
+//! | `prefix`         | nodes, rules        | `value`                            |
+//! | `replace`        | nodes, rules        | `from`, `to`                       |
+//! | `set`            | rules, edges        | `binding`, `value`                 |
+//! | `set`            | variables           | `value`                            |
+//! | `add-dependency` | edges               | `path`, `dependency`               |
+//! | `delete`         | all                 |                                    |
+//!
+//! With a `regex` condition, `$1` or `${name}` in the `value` of `rename` is
+//! the text of that group of the match. `set` values are written the way
+//! they are in a manifest, with `$` escapes and variables. `dependency` is a
+//! `kind`, `implicit-input` by default. Deleting a node removes it from the
+//! edges it's a path of, or from those matching `rule` and `kind`.
+//!
+//! Rewrites select items of the loaded manifest, not of what earlier
+//! rewrites made of it; an item is only renamed by the first rewrite
+//! renaming it. [`Recipe::apply`] fails when a rewrite changes something an
+//! earlier one deleted, or the other way around.
+
+use crate::{
+    lexer::LOC_INVALID, ChangeList, EdgeKey, EscapeError, EvalString, NodeKey, PathKind, RuleKey,
+    ScopeKey,
+};
+use fs_err as fs;
+use glob::Pattern;
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    path::Path,
+};
+
+#[derive(Debug)]
+pub enum RecipeError {
+    Io(io::Error),
+    /// Not TOML, or not the tables and fields of a recipe.
+    Malformed(String),
+    /// A rewrite doesn't make sense; `rewrite` counts from 1.
+    Invalid {
+        rewrite: usize,
+        message: String,
+    },
+    /// A rewrite edits what an earlier one already edited.
+    Conflict {
+        rewrite: usize,
+        message: String,
+    },
+    Escape(EscapeError),
+}
+impl fmt::Display for RecipeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecipeError::Io(e) => write!(f, "{e}"),
+            RecipeError::Malformed(e) => write!(f, "malformed recipe: {e}"),
+            RecipeError::Invalid { rewrite, message }
+            | RecipeError::Conflict { rewrite, message } => {
+                write!(f, "rewrite {rewrite}: {message}")
+            }
+            RecipeError::Escape(e) => write!(f, "{e}"),
+        }
+    }
+}
+impl std::error::Error for RecipeError {}
+impl From<io::Error> for RecipeError {
+    fn from(e: io::Error) -> Self {
+        RecipeError::Io(e)
+    }
+}
+impl From<EscapeError> for RecipeError {
+    fn from(e: EscapeError) -> Self {
+        RecipeError::Escape(e)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRecipe {
+    #[serde(default)]
+    rewrite: Vec<RawRewrite>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRewrite {
+    #[serde(rename = "match")]
+    target: Target,
+    glob: Option<String>,
+    regex: Option<String>,
+    rule: Option<String>,
+    kind: Option<DependencyKind>,
+    action: ActionName,
+    value: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    binding: Option<String>,
+    path: Option<String>,
+    dependency: Option<DependencyKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Target {
+    Node,
+    Rule,
+    Edge,
+    Variable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ActionName {
+    Rename,
+    Prefix,
+    Replace,
+    Set,
+    AddDependency,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum DependencyKind {
+    Output,
+    ImplicitOutput,
+    Input,
+    ImplicitInput,
+    OrderOnlyInput,
+    Validation,
+}
+impl From<DependencyKind> for PathKind {
+    fn from(kind: DependencyKind) -> Self {
+        match kind {
+            DependencyKind::Output => PathKind::Out,
+            DependencyKind::ImplicitOutput => PathKind::ImplicitOut,
+            DependencyKind::Input => PathKind::In,
+            DependencyKind::ImplicitInput => PathKind::ImplicitIn,
+            DependencyKind::OrderOnlyInput => PathKind::OrderOnlyIn,
+            DependencyKind::Validation => PathKind::Validation,
+        }
+    }
+}
+
+enum Action {
+    Rename(String),
+    Prefix(String),
+    Replace {
+        from: String,
+        to: String,
+    },
+    Set {
+        binding: Option<String>,
+        value: EvalString,
+    },
+    AddDependency {
+        kind: PathKind,
+        path: String,
+    },
+    Delete,
+}
+
+struct Rewrite {
+    target: Target,
+    glob: Option<Pattern>,
+    regex: Option<Regex>,
+    rule: Option<String>,
+    kind: Option<PathKind>,
+    action: Action,
+}
+impl Rewrite {
+    fn from_raw(raw: RawRewrite) -> Result<Rewrite, String> {
+        let target_name = match raw.target {
+            Target::Node => "nodes",
+            Target::Rule => "rules",
+            Target::Edge => "edges",
+            Target::Variable => "variables",
+        };
+        let on_paths = matches!(raw.target, Target::Node | Target::Edge);
+        if !on_paths && raw.rule.is_some() {
+            return Err(format!("`rule` doesn't apply to {}", target_name));
+        }
+        if !on_paths && raw.kind.is_some() {
+            return Err(format!("`kind` doesn't apply to {}", target_name));
+        }
+
+        let (action_name, allowed): (_, &[_]) = match raw.action {
+            ActionName::Rename => ("rename", &[Target::Node, Target::Rule]),
+            ActionName::Prefix => ("prefix", &[Target::Node, Target::Rule]),
+            ActionName::Replace => ("replace", &[Target::Node, Target::Rule]),
+            ActionName::Set => ("set", &[Target::Rule, Target::Edge, Target::Variable]),
+            ActionName::AddDependency => ("add-dependency", &[Target::Edge]),
+            ActionName::Delete => ("delete", &[raw.target]),
+        };
+        if !allowed.contains(&raw.target) {
+            return Err(format!("can't {} {}", action_name, target_name));
+        }
+
+        let params: &[_] = match (raw.action, raw.target) {
+            (ActionName::Rename | ActionName::Prefix, _) => &["value"],
+            (ActionName::Replace, _) => &["from", "to"],
+            (ActionName::Set, Target::Variable) => &["value"],
+            (ActionName::Set, _) => &["binding", "value"],
+            (ActionName::AddDependency, _) => &["path", "dependency"],
+            (ActionName::Delete, _) => &[],
+        };
+        let given = [
+            ("value", raw.value.is_some()),
+            ("from", raw.from.is_some()),
+            ("to", raw.to.is_some()),
+            ("binding", raw.binding.is_some()),
+            ("path", raw.path.is_some()),
+            ("dependency", raw.dependency.is_some()),
+        ];
+        for (name, is_given) in given {
+            if is_given && !params.contains(&name) {
+                return Err(format!("`{}` doesn't apply to `{}`", name, action_name));
+            }
+            if !is_given && params.contains(&name) && name != "dependency" {
+                return Err(format!("`{}` needs `{}`", action_name, name));
+            }
+        }
+
+        let action = match raw.action {
+            ActionName::Rename => Action::Rename(raw.value.unwrap()),
+            ActionName::Prefix => Action::Prefix(raw.value.unwrap()),
+            ActionName::Replace => Action::Replace {
+                from: raw.from.unwrap(),
+                to: raw.to.unwrap(),
+            },
+            ActionName::Set => Action::Set {
+                binding: raw.binding,
+                value: EvalString::parse_value(&raw.value.unwrap())
+                    .map_err(|e| format!("bad `value`: {}", e))?,
+            },
+            ActionName::AddDependency => Action::AddDependency {
+                kind: raw
+                    .dependency
+                    .unwrap_or(DependencyKind::ImplicitInput)
+                    .into(),
+                path: raw.path.unwrap(),
+            },
+            ActionName::Delete => Action::Delete,
+        };
+
+        let glob = match raw.glob {
+            Some(glob) => Some(Pattern::new(&glob).map_err(|e| format!("bad `glob`: {}", e))?),
+            None => None,
+        };
+        let regex = match raw.regex {
+            Some(regex) => Some(Regex::new(&regex).map_err(|e| format!("bad `regex`: {}", e))?),
+            None => None,
+        };
+        Ok(Rewrite {
+            target: raw.target,
+            glob,
+            regex,
+            rule: raw.rule,
+            kind: raw.kind.map(PathKind::from),
+            action,
+        })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.glob.as_ref().is_none_or(|x| x.matches(name))
+            && self.regex.as_ref().is_none_or(|x| x.is_match(name))
+    }
+
+    /// The new name of a renamed node or rule, if it changes.
+    fn new_name(&self, name: &str) -> Option<String> {
+        let new_name = match &self.action {
+            Action::Rename(value) => match self.regex.as_ref().and_then(|x| x.captures(name)) {
+                Some(captures) => {
+                    let mut new_name = String::new();
+                    captures.expand(value, &mut new_name);
+                    new_name
+                }
+                None => value.clone(),
+            },
+            Action::Prefix(value) => format!("{}{}", value, name),
+            Action::Replace { from, to } => name.replace(from.as_str(), to),
+            _ => return None,
+        };
+        (new_name != name).then_some(new_name)
+    }
+}
+
+/// A list of rewrites, see the [module documentation](self).
+pub struct Recipe {
+    rewrites: Vec<Rewrite>,
+}
+
+impl Recipe {
+    pub fn parse(text: &str) -> Result<Recipe, RecipeError> {
+        let raw: RawRecipe =
+            toml::from_str(text).map_err(|e| RecipeError::Malformed(e.to_string()))?;
+        let rewrites = raw
+            .rewrite
+            .into_iter()
+            .enumerate()
+            .map(|(n, x)| {
+                Rewrite::from_raw(x).map_err(|message| RecipeError::Invalid {
+                    rewrite: n + 1,
+                    message,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Recipe { rewrites })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Recipe, RecipeError> {
+        Recipe::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Adds the rewrites to `changelist`; returns how many items were
+    /// changed, an item counting once per rewrite changing it. Fails if a
+    /// rewrite changes what an earlier one deleted, deletes what an earlier
+    /// one changed, or otherwise edits the same text.
+    pub fn apply(&self, changelist: &mut ChangeList) -> Result<usize, RecipeError> {
+        let data = &changelist.ninja().data;
+        let mut uses: HashMap<NodeKey, Vec<(EdgeKey, PathKind)>> = HashMap::new();
+        for (key, edge) in &data.edges {
+            for (kind, path) in edge.all_paths() {
+                uses.entry(path.elem).or_default().push((key, kind));
+            }
+        }
+        let mut history = History::default();
+        let mut renamed_nodes = HashSet::new();
+        let mut renamed_rules = HashSet::new();
+
+        let mut count = 0;
+        for (n, rewrite) in self.rewrites.iter().enumerate() {
+            let n = n + 1;
+            let delete = matches!(rewrite.action, Action::Delete);
+            match rewrite.target {
+                Target::Node => {
+                    for (key, node) in &data.nodes {
+                        if node.locs.is_empty() || !rewrite.matches(&node.path) {
+                            continue;
+                        }
+                        let mut matched: Vec<_> = uses
+                            .get(&key)
+                            .into_iter()
+                            .flatten()
+                            .filter(|&&(edge, kind)| {
+                                let rule = data.rules[data.edges[edge].rule].name.elem;
+                                rewrite.kind.is_none_or(|x| x == kind)
+                                    && rewrite.rule.as_ref().is_none_or(|x| x == rule)
+                            })
+                            .collect();
+                        if matched.is_empty() && (rewrite.kind.is_some() || rewrite.rule.is_some())
+                        {
+                            continue;
+                        }
+                        let item = Item::Node(key);
+                        if delete {
+                            if history.record(n, item, true, &node.path)? {
+                                matched.dedup();
+                                for &&(edge, kind) in &matched {
+                                    changelist.remove_edge_path(edge, kind, key);
+                                }
+                                count += 1;
+                            }
+                        } else if let Some(new_path) = rewrite.new_name(&node.path) {
+                            if !renamed_nodes.contains(&key)
+                                && history.record(n, item, false, &node.path)?
+                            {
+                                renamed_nodes.insert(key);
+                                changelist.rename_node(key, &new_path)?;
+                                count += 1;
+                            }
+                        }
+                    }
+                }
+                Target::Rule => {
+                    for (key, rule) in &data.rules {
+                        let name = rule.name.elem;
+                        if rule.file.is_none() || !rewrite.matches(name) {
+                            continue;
+                        }
+                        let new_name = match &rewrite.action {
+                            Action::Set { .. } | Action::Delete => None,
+                            _ => match rewrite.new_name(name) {
+                                Some(new_name) if !renamed_rules.contains(&key) => Some(new_name),
+                                _ => continue,
+                            },
+                        };
+                        if !history.record(n, Item::Rule(key), delete, name)? {
+                            continue;
+                        }
+                        match &rewrite.action {
+                            Action::Set { binding, value } => {
+                                let binding = binding.as_deref().unwrap();
+                                changelist.set_rule_binding(key, binding, value)?;
+                            }
+                            Action::Delete => changelist.remove_rule(key),
+                            _ => {
+                                renamed_rules.insert(key);
+                                changelist.rename_rule(key, new_name.unwrap());
+                            }
+                        }
+                        count += 1;
+                    }
+                }
+                Target::Edge => {
+                    for (key, edge) in &data.edges {
+                        if edge.loc == LOC_INVALID {
+                            continue;
+                        }
+                        let rule = data.rules[edge.rule].name.elem;
+                        if rewrite.rule.as_ref().is_some_and(|x| x != rule) {
+                            continue;
+                        }
+                        if rewrite.glob.is_some() || rewrite.regex.is_some() {
+                            let kinds = match rewrite.kind {
+                                Some(kind) => vec![kind],
+                                None => vec![PathKind::Out, PathKind::ImplicitOut],
+                            };
+                            let matched = kinds
+                                .into_iter()
+                                .flat_map(|x| edge.paths(x))
+                                .any(|x| rewrite.matches(&data.nodes[x.elem].path));
+                            if !matched {
+                                continue;
+                            }
+                        }
+                        let name = edge
+                            .outs
+                            .first()
+                            .map_or("", |x| data.nodes[x.elem].path.as_str());
+                        if !history.record(n, Item::Edge(key), delete, name)? {
+                            continue;
+                        }
+                        match &rewrite.action {
+                            Action::Set { binding, value } => {
+                                let binding = binding.as_deref().unwrap();
+                                changelist.set_edge_binding(key, binding, value)?;
+                            }
+                            Action::AddDependency { kind, path } => {
+                                changelist.add_edge_path(key, *kind, path)?;
+                            }
+                            _ => changelist.remove_edge(key),
+                        }
+                        count += 1;
+                    }
+                }
+                Target::Variable => {
+                    for (scope, vars) in &data.scopes {
+                        for var in &vars.vars {
+                            let name = &var.name.elem;
+                            if var.name.loc() == LOC_INVALID || !rewrite.matches(name) {
+                                continue;
+                            }
+                            if !history.record(n, Item::Variable(scope, name), delete, name)? {
+                                continue;
+                            }
+                            match &rewrite.action {
+                                Action::Set { value, .. } => {
+                                    changelist.set_variable(scope, name, value)?;
+                                }
+                                _ => changelist.remove_variable(scope, name),
+                            }
+                            count += 1;
+                        }
+                    }
+                }
+            }
+            // What the checks above don't see, e.g. renaming a path of a
+            // deleted edge.
+            changelist
+                .check_overlaps()
+                .map_err(|e| RecipeError::Conflict {
+                    rewrite: n,
+                    message: e.to_string(),
+                })?;
+        }
+        Ok(count)
+    }
+}
+
+/// Something a rewrite changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Item<'a> {
+    Node(NodeKey),
+    Rule(RuleKey),
+    Edge(EdgeKey),
+    Variable(ScopeKey, &'a str),
+}
+
+/// The first rewrite changing, and the one deleting, each item.
+#[derive(Default)]
+struct History<'a> {
+    changed: HashMap<Item<'a>, usize>,
+    deleted: HashMap<Item<'a>, usize>,
+}
+impl<'a> History<'a> {
+    /// Records that rewrite `n` changes or deletes `item`, named `name` in
+    /// errors. Returns false for deleting it again.
+    fn record(
+        &mut self,
+        n: usize,
+        item: Item<'a>,
+        delete: bool,
+        name: &str,
+    ) -> Result<bool, RecipeError> {
+        let conflict = |earlier: usize, what: &str| RecipeError::Conflict {
+            rewrite: n,
+            message: format!("`{}` was {} by rewrite {}", name, what, earlier),
+        };
+        if let Some(&earlier) = self.deleted.get(&item) {
+            return match delete {
+                true => Ok(false),
+                false => Err(conflict(earlier, "deleted")),
+            };
+        }
+        if delete {
+            if let Some(&earlier) = self.changed.get(&item) {
+                return Err(conflict(earlier, "changed"));
+            }
+            self.deleted.insert(item, n);
+        } else {
+            self.changed.entry(item).or_insert(n);
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    changelist::reparse_with,
+    flatten::Flattener,
+    validate::evaluated_differences,
+    writer::{blank_line, write_default},
+    Data, Diagnostic, EdgeKey, EvalString, FileKind, Ninja,
+};
+use std::{collections::HashMap, path::PathBuf};
+
+impl Ninja {
+    /// Splits the manifest into a top level file and one `include` or
+    /// `subninja` file per group, as chosen by `group`. Edges it returns
+    /// `None` for stay in the top level file, as do variables, pools, rules
+    /// and `default` statements; group paths are relative to the working
+    /// directory, like in the manifest. Nested scopes are flattened first,
+    /// see [`Data::flatten`](crate::Data::flatten). `kind` can't be
+    /// [`FileKind::Root`].
+    ///
+    /// Grouping by the directory of the first output gives per-directory
+    /// files, grouping by a target's dependencies per-target ones.
+    ///
+    /// Nothing is written: the new texts are returned, top level first, once
+    /// they are parsed in memory and found to evaluate to the same graph.
+    pub fn split<F>(
+        &self,
+        kind: FileKind,
+        mut group: F,
+    ) -> Result<Vec<(PathBuf, String)>, Diagnostic>
+    where
+        F: FnMut(&Data, EdgeKey) -> Option<String>,
+    {
+        let root_path = self.root_path().to_path_buf();
+        let error = |e: &dyn std::fmt::Display| Diagnostic::for_file(&root_path, e.to_string());
+        let keyword = match kind {
+            FileKind::Include => "include",
+            FileKind::Subninja => "subninja",
+            FileKind::Root => return Err(error(&"edges can only be split into included files")),
+        };
+
+        // Group paths and the root path are both relative to the working
+        // directory, so `build.ninja` and `./build.ninja` are the same file.
+        let root_absolute = std::path::absolute(&root_path).map_err(|e| error(&e))?;
+        let flattener = Flattener::new(&self.data);
+        let mut root = String::new();
+        let mut groups: Vec<(PathBuf, String)> = Vec::new();
+        let mut group_index = HashMap::new();
+        let mut ungrouped = String::new();
+        for edge in self.data.edges.keys() {
+            let Some(path) = group(&self.data, edge) else {
+                flattener
+                    .write_edge(&mut ungrouped, edge)
+                    .map_err(|e| error(&e))?;
+                continue;
+            };
+            let path = PathBuf::from(path);
+            if std::path::absolute(&path).is_ok_and(|x| x == root_absolute) {
+                return Err(error(&"a group can't be the top level file"));
+            }
+            let n = *group_index.entry(path.clone()).or_insert_with(|| {
+                groups.push((path, String::new()));
+                groups.len() - 1
+            });
+            flattener
+                .write_edge(&mut groups[n].1, edge)
+                .map_err(|e| error(&e))?;
+        }
+
+        let mut section = flattener.write_header(&mut root).map_err(|e| error(&e))?;
+        blank_line(&mut root, &mut section);
+        for (path, _) in &groups {
+            let path = EvalString::literal(path.to_string_lossy())
+                .to_path()
+                .map_err(|e| error(&e))?;
+            root += &format!("{} {}\n", keyword, path);
+            section = true;
+        }
+        blank_line(&mut root, &mut section);
+        if !ungrouped.is_empty() {
+            root += &ungrouped;
+            section = true;
+        }
+        blank_line(&mut root, &mut section);
+        for default in &self.data.defaults {
+            write_default(&mut root, &self.data, default).map_err(|e| error(&e))?;
+        }
+        if root.ends_with("\n\n") {
+            root.pop();
+        }
+
+        let mut files = vec![(root_path.clone(), root)];
+        files.extend(groups);
+
+        let split = reparse_with(self, files.iter().cloned())?;
+        let differences = evaluated_differences(self, &split);
+        if !differences.is_empty() {
+            return Err(error(&format!(
+                "splitting would change the graph: {}",
+                differences.join("; ")
+            )));
+        }
+        Ok(files)
+    }
+}
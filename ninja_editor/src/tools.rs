@@ -0,0 +1,231 @@
+use crate::{eval::shell_escape, Data, EdgeKey, NodeKey, PathKind};
+use std::collections::{BTreeSet, HashSet};
+
+/// What `ninja -t targets` lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetsMode<'a> {
+    /// The root nodes and their inputs, to this depth; 0 is unlimited.
+    /// `ninja -t targets` is `Depth(1)`.
+    Depth(usize),
+    /// Every output with its rule.
+    All,
+    /// The outputs of edges of this rule, sorted; `-t targets rule <name>`.
+    Rule(&'a str),
+    /// Inputs that nothing generates; `-t targets rule`.
+    Sources,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InputsOptions {
+    /// Keeps the order of [`Data::transitive_inputs`] instead of sorting,
+    /// like `-d`.
+    pub dependency_order: bool,
+    /// Quotes the paths for a POSIX shell; `-E` turns this off.
+    pub shell_escape: bool,
+    /// Ends the paths with NULs instead of newlines, like `-0`.
+    pub print0: bool,
+}
+impl Default for InputsOptions {
+    fn default() -> Self {
+        InputsOptions {
+            dependency_order: false,
+            shell_escape: true,
+            print0: false,
+        }
+    }
+}
+
+impl Data<'_> {
+    /// The outputs that no edge has as an input, in the order of their
+    /// edges, which is what ninja builds without `default` statements.
+    pub fn root_nodes(&self) -> Vec<NodeKey> {
+        self.edges
+            .values()
+            .flat_map(|x| x.outputs())
+            .filter(|&x| self.nodes[x].out_edges.is_empty())
+            .collect()
+    }
+
+    /// The targets ninja builds when given none: those of the `default`
+    /// statements, or else [`Data::root_nodes`].
+    pub fn default_nodes(&self) -> Vec<NodeKey> {
+        match self.defaults.is_empty() {
+            true => self.root_nodes(),
+            false => self
+                .defaults
+                .iter()
+                .flat_map(|x| &x.targets)
+                .map(|x| x.elem)
+                .collect(),
+        }
+    }
+
+    /// The inputs `targets` depend on, directly or not, each after its own
+    /// inputs. Outputs of `phony` edges are left out, but not their inputs.
+    pub fn transitive_inputs(&self, targets: &[NodeKey]) -> Vec<NodeKey> {
+        let inputs = |node: NodeKey| -> Vec<NodeKey> {
+            let edge = self.nodes[node].in_edge;
+            edge.map_or(Vec::new(), |x| {
+                self.edges[x].inputs().map(|(_, x)| x).collect()
+            })
+        };
+        let mut ret = Vec::new();
+        let mut visited = HashSet::new();
+        for &target in targets {
+            let mut stack = vec![(target, inputs(target), 0)];
+            while let Some((node, next, n)) = stack.last_mut() {
+                let Some(&input) = next.get(*n) else {
+                    let node = *node;
+                    stack.pop();
+                    let phony = self.nodes[node].in_edge.is_some_and(|x| self.is_phony(x));
+                    if !stack.is_empty() && !phony {
+                        ret.push(node);
+                    }
+                    continue;
+                };
+                *n += 1;
+                if visited.insert(input) {
+                    stack.push((input, inputs(input), 0));
+                }
+            }
+        }
+        ret
+    }
+
+    /// What `ninja -t query` prints for `targets`.
+    pub fn tool_query(&self, targets: &[NodeKey]) -> String {
+        let mut out = String::new();
+        for &target in targets {
+            let node = &self.nodes[target];
+            out += &format!("{}:\n", node.path);
+            if let Some(key) = node.in_edge {
+                let edge = &self.edges[key];
+                out += &format!("  input: {}\n", self.rules[edge.rule].name.elem);
+                for (kind, input) in edge.inputs() {
+                    let label = match kind {
+                        PathKind::ImplicitIn => "| ",
+                        PathKind::OrderOnlyIn => "|| ",
+                        _ => "",
+                    };
+                    out += &format!("    {}{}\n", label, self.nodes[input].path);
+                }
+                if !edge.validations.is_empty() {
+                    out += "  validations:\n";
+                    for i in &edge.validations {
+                        out += &format!("    {}\n", self.nodes[i.elem].path);
+                    }
+                }
+            }
+            out += "  outputs:\n";
+            for &edge in &node.out_edges {
+                for output in self.edges[edge].outputs() {
+                    out += &format!("    {}\n", self.nodes[output].path);
+                }
+            }
+            let validated: Vec<_> = self
+                .edges
+                .values()
+                .filter(|x| x.validations.iter().any(|x| x.elem == target))
+                .collect();
+            if !validated.is_empty() {
+                out += "  validation for:\n";
+                for output in validated.iter().flat_map(|x| x.outputs()) {
+                    out += &format!("    {}\n", self.nodes[output].path);
+                }
+            }
+        }
+        out
+    }
+
+    /// What `ninja -t inputs` prints for `targets`, or for
+    /// [`Data::default_nodes`] without targets.
+    pub fn tool_inputs(&self, targets: &[NodeKey], options: &InputsOptions) -> String {
+        let targets = match targets.is_empty() {
+            true => self.default_nodes(),
+            false => targets.to_vec(),
+        };
+        let mut inputs: Vec<_> = self
+            .transitive_inputs(&targets)
+            .into_iter()
+            .map(|x| {
+                let path = &self.nodes[x].path;
+                match options.shell_escape {
+                    true => shell_escape(path),
+                    false => path.clone(),
+                }
+            })
+            .collect();
+        if !options.dependency_order {
+            inputs.sort();
+        }
+        let terminator = if options.print0 { '\0' } else { '\n' };
+        let mut out = String::new();
+        for i in inputs {
+            out += &i;
+            out.push(terminator);
+        }
+        out
+    }
+
+    /// What `ninja -t targets` prints.
+    pub fn tool_targets(&self, mode: TargetsMode) -> String {
+        let mut out = String::new();
+        match mode {
+            TargetsMode::Depth(depth) => {
+                self.write_targets(&mut out, &self.root_nodes(), depth, 0);
+            }
+            TargetsMode::All => {
+                for edge in self.edges.values() {
+                    let rule = self.rules[edge.rule].name.elem;
+                    for output in edge.outputs() {
+                        out += &format!("{}: {}\n", self.nodes[output].path, rule);
+                    }
+                }
+            }
+            TargetsMode::Rule(name) => {
+                let outputs: BTreeSet<_> = self
+                    .edges
+                    .values()
+                    .filter(|x| self.rules[x.rule].name.elem == name)
+                    .flat_map(|x| x.outputs())
+                    .map(|x| self.nodes[x].path.as_str())
+                    .collect();
+                for i in outputs {
+                    out += &format!("{}\n", i);
+                }
+            }
+            TargetsMode::Sources => {
+                for edge in self.edges.values() {
+                    for (_, input) in edge.inputs() {
+                        if self.nodes[input].in_edge.is_none() {
+                            out += &format!("{}\n", self.nodes[input].path);
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn write_targets(&self, out: &mut String, nodes: &[NodeKey], depth: usize, indent: usize) {
+        for &key in nodes {
+            let node = &self.nodes[key];
+            *out += &"  ".repeat(indent);
+            match node.in_edge {
+                Some(edge) => {
+                    let edge = &self.edges[edge];
+                    *out += &format!("{}: {}\n", node.path, self.rules[edge.rule].name.elem);
+                    if depth != 1 {
+                        let inputs: Vec<_> = edge.inputs().map(|(_, x)| x).collect();
+                        self.write_targets(out, &inputs, depth.saturating_sub(1), indent + 1);
+                    }
+                }
+                None => *out += &format!("{}\n", node.path),
+            }
+        }
+    }
+
+    fn is_phony(&self, edge: EdgeKey) -> bool {
+        self.rules[self.edges[edge].rule].file.is_none()
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    changelist::{reparse, GeneratedFile},
+    lexer::Location,
+    parser::RULE_VARS,
+    Data, Diagnostic, Ninja, SourceId,
+};
+use std::{collections::HashMap, fmt, ops::Range, path::PathBuf};
+
+/// The changes would leave the manifest broken; nothing was written.
+#[derive(Debug)]
+pub struct ValidationError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for ValidationError {}
+
+pub(crate) fn validate(
+    ninja: &Ninja,
+    files: &HashMap<SourceId, GeneratedFile>,
+) -> Result<(), ValidationError> {
+    let new = reparse(ninja, files).map_err(|e| ValidationError {
+        diagnostics: vec![e],
+    })?;
+
+    // A node may only come or go if one of its occurrences lies within an edit.
+    let changed_old: Vec<Location> = files
+        .values()
+        .flat_map(|x| x.changed.iter().map(|(loc, _)| *loc))
+        .collect();
+    let is_changed_old = |loc: &Location| {
+        changed_old
+            .iter()
+            .any(|x| x.source_id == loc.source_id && x.start <= loc.start && loc.stop <= x.stop)
+    };
+    let changed_new: HashMap<&PathBuf, Vec<&Range<usize>>> = files
+        .iter()
+        .map(|(&id, file)| {
+            let ranges = file.changed.iter().map(|(_, range)| range).collect();
+            (&ninja.sm.get(id).path, ranges)
+        })
+        .collect();
+    let is_changed_new = |loc: &Location| {
+        let path = &new.sm.get(loc.source_id).path;
+        changed_new.get(path).is_some_and(|ranges| {
+            ranges
+                .iter()
+                .any(|x| x.start <= loc.start && loc.stop <= x.end)
+        })
+    };
+
+    let mut diagnostics = Vec::new();
+    for node in ninja.data.nodes.values() {
+        if new.data.node(&node.path).is_some() || node.locs.iter().any(is_changed_old) {
+            continue;
+        }
+        let Some(&loc) = node.locs.first() else {
+            continue;
+        };
+        diagnostics.push(Diagnostic::at(
+            &ninja.sm,
+            loc,
+            format!("node `{}` is no longer part of the graph", node.path),
+        ));
+    }
+    for node in new.data.nodes.values() {
+        if ninja.data.node(&node.path).is_some() || node.locs.iter().any(is_changed_new) {
+            continue;
+        }
+        let Some(&loc) = node.locs.first() else {
+            continue;
+        };
+        diagnostics.push(Diagnostic::at(
+            &new.sm,
+            loc,
+            format!("node `{}` was not part of the graph before", node.path),
+        ));
+    }
+
+    if diagnostics.is_empty() {
+        return Ok(());
+    }
+    diagnostics.sort_by(|a, b| (&a.path, a.line, a.column).cmp(&(&b.path, b.line, b.column)));
+    Err(ValidationError { diagnostics })
+}
+
+/// Human readable differences between the graphs of two manifests, ignoring
+/// where things are written.
+pub(crate) fn graph_differences(a: &Ninja, b: &Ninja) -> Vec<String> {
+    let (a, b) = (&a.data, &b.data);
+    let mut differences = Vec::new();
+    let mut compare = |what: &str, f: fn(&Data) -> Vec<String>| {
+        if f(a) != f(b) {
+            differences.push(format!("the {what} differ"));
+        }
+    };
+
+    compare("nodes", |data| {
+        sorted(data.nodes.values().map(|x| x.path.clone()))
+    });
+    compare("edges", |data| {
+        sorted(data.edges.values().map(|edge| {
+            let mut signature = data.rules[edge.rule].name.elem.to_string();
+            for (kind, node) in edge.all_paths() {
+                signature += &format!(" {:?}:{}", kind, data.nodes[node.elem].path);
+            }
+            for i in &edge.bindings {
+                signature += &format!(" {}={}", i.name.elem, i.value.elem);
+            }
+            signature
+        }))
+    });
+    compare("rules", |data| {
+        sorted(data.rules.values().map(|rule| {
+            let mut signature = rule.name.elem.to_string();
+            for i in &rule.bindings {
+                signature += &format!(" {}={:?}", i.name.elem, i.value.elem);
+            }
+            signature
+        }))
+    });
+    compare("pools", |data| {
+        sorted(
+            data.pools
+                .values()
+                .map(|x| format!("{}={}", x.name.elem, x.depth.elem)),
+        )
+    });
+    compare("variables", |data| {
+        data.scopes
+            .values()
+            .map(|scope| {
+                let vars = scope
+                    .vars
+                    .iter()
+                    .map(|x| format!("{}={}", x.name.elem, x.value.elem));
+                sorted(vars).join("\n")
+            })
+            .collect()
+    });
+    compare("defaults", |data| {
+        sorted(
+            data.defaults
+                .iter()
+                .flat_map(|x| &x.targets)
+                .map(|x| data.nodes[x.elem].path.clone()),
+        )
+    });
+
+    differences
+}
+
+/// Like [`graph_differences`], but only looks at what ninja would run: edges
+/// are compared by their paths and evaluated reserved variables, so rules and
+/// variables may be renamed or moved between scopes.
+pub(crate) fn evaluated_differences(a: &Ninja, b: &Ninja) -> Vec<String> {
+    let (a, b) = (&a.data, &b.data);
+    let mut differences = Vec::new();
+    let mut compare = |what: &str, f: fn(&Data) -> Vec<String>| {
+        if f(a) != f(b) {
+            differences.push(format!("the {what} differ"));
+        }
+    };
+
+    compare("nodes", |data| {
+        sorted(data.nodes.values().map(|x| x.path.clone()))
+    });
+    compare("edges", |data| {
+        sorted(data.edges.iter().map(|(key, edge)| {
+            let mut signature = String::new();
+            for (kind, node) in edge.all_paths() {
+                signature += &format!("{:?}:{} ", kind, data.nodes[node.elem].path);
+            }
+            for name in RULE_VARS {
+                signature += &format!(" {}={}", name, data.evaluate_edge(key, name));
+            }
+            signature
+        }))
+    });
+    compare("pools", |data| {
+        sorted(
+            data.pools
+                .values()
+                .map(|x| format!("{}={}", x.name.elem, x.depth.elem)),
+        )
+    });
+    compare("defaults", |data| {
+        sorted(
+            data.defaults
+                .iter()
+                .flat_map(|x| &x.targets)
+                .map(|x| data.nodes[x.elem].path.clone()),
+        )
+    });
+
+    differences
+}
+
+fn sorted<I: Iterator<Item = String>>(iter: I) -> Vec<String> {
+    let mut ret: Vec<_> = iter.collect();
+    ret.sort();
+    ret
+}
@@ -0,0 +1,218 @@
+use crate::{
+    Data, DefaultStatement, Edge, EscapeError, EvalString, FileKey, FileKind, PathKind, Pool, Rule,
+};
+
+impl Data<'_> {
+    /// Writes every file of the manifest from the model alone, root first.
+    ///
+    /// Paths, top level variables and `build` bindings are written evaluated,
+    /// rules keep their variable references. Each file gets its variables,
+    /// pools, rules, `include`/`subninja` statements, `build` statements and
+    /// `default` statements, in that order.
+    pub fn write(&self) -> Result<Vec<(String, String)>, EscapeError> {
+        self.files
+            .iter()
+            .map(|(key, file)| Ok((file.path.elem.clone(), self.write_file(key)?)))
+            .collect()
+    }
+
+    pub fn write_file(&self, file: FileKey) -> Result<String, EscapeError> {
+        let mut out = String::new();
+        let scope = &self.scopes[self.files[file].scope];
+
+        let mut section = false;
+        // Only the last assignment matters, everything else is evaluated.
+        for var in scope.vars.iter().filter(|x| x.file == file) {
+            write_var(&mut out, &var.name.elem, &var.value.elem)?;
+            section = true;
+        }
+
+        for pool in self.pools.values().filter(|x| x.file == Some(file)) {
+            blank_line(&mut out, &mut section);
+            write_pool(&mut out, pool);
+            section = true;
+        }
+
+        for rule in self.rules.values().filter(|x| x.file == Some(file)) {
+            blank_line(&mut out, &mut section);
+            write_rule(&mut out, rule.name.elem, rule)?;
+            section = true;
+        }
+
+        blank_line(&mut out, &mut section);
+        for &child in &self.files[file].children {
+            let child = &self.files[child];
+            let keyword = match child.kind {
+                FileKind::Subninja => "subninja",
+                _ => "include",
+            };
+            out += &format!("{} {}\n", keyword, path(&child.path.elem)?);
+            section = true;
+        }
+
+        blank_line(&mut out, &mut section);
+        for edge in self.edges.values().filter(|x| x.file == file) {
+            write_edge(&mut out, self, edge, self.rules[edge.rule].name.elem, &[])?;
+            section = true;
+        }
+
+        blank_line(&mut out, &mut section);
+        for default in self.defaults.iter().filter(|x| x.file == file) {
+            write_default(&mut out, self, default)?;
+        }
+
+        if out.ends_with("\n\n") {
+            out.pop();
+        }
+        Ok(out)
+    }
+}
+
+pub(crate) fn write_var(out: &mut String, name: &str, value: &str) -> Result<(), EscapeError> {
+    let value = EvalString::literal(value).to_value()?;
+    assignment(out, "", name, &value);
+    Ok(())
+}
+
+pub(crate) fn write_pool(out: &mut String, pool: &Pool) {
+    *out += &format!("pool {}\n  depth = {}\n", pool.name.elem, pool.depth.elem);
+}
+
+pub(crate) fn write_rule(out: &mut String, name: &str, rule: &Rule) -> Result<(), EscapeError> {
+    *out += &format!("rule {}\n", name);
+    for i in &rule.bindings {
+        assignment(out, "  ", &i.name.elem, &i.value.elem.to_value()?);
+    }
+    Ok(())
+}
+
+/// Writes a `build` statement; `extra` bindings follow the edge's own.
+pub(crate) fn write_edge(
+    out: &mut String,
+    data: &Data,
+    edge: &Edge,
+    rule: &str,
+    extra: &[(&str, &str)],
+) -> Result<(), EscapeError> {
+    *out += "build";
+    for kind in PathKind::ALL {
+        let separator = match kind {
+            PathKind::ImplicitOut | PathKind::ImplicitIn => " |",
+            PathKind::OrderOnlyIn => " ||",
+            PathKind::Validation => " |@",
+            PathKind::Out | PathKind::In => "",
+        };
+        let paths = edge.paths(kind);
+        if !paths.is_empty() {
+            *out += separator;
+        }
+        for i in paths {
+            out.push(' ');
+            *out += &path(&data.nodes[i.elem].path)?;
+        }
+        if kind == PathKind::ImplicitOut {
+            *out += &format!(": {}", rule);
+        }
+    }
+    out.push('\n');
+
+    let bindings = edge
+        .bindings
+        .iter()
+        .map(|x| (x.name.elem.as_str(), x.value.elem.as_str()));
+    for (name, value) in bindings.chain(extra.iter().copied()) {
+        let value = EvalString::literal(value).to_value()?;
+        assignment(out, "  ", name, &value);
+    }
+    Ok(())
+}
+
+pub(crate) fn write_default(
+    out: &mut String,
+    data: &Data,
+    default: &DefaultStatement,
+) -> Result<(), EscapeError> {
+    *out += "default";
+    for i in &default.targets {
+        out.push(' ');
+        *out += &path(&data.nodes[i.elem].path)?;
+    }
+    out.push('\n');
+    Ok(())
+}
+
+fn path(path: &str) -> Result<String, EscapeError> {
+    EvalString::literal(path).to_path()
+}
+
+fn assignment(out: &mut String, indent: &str, name: &str, value: &str) {
+    *out += indent;
+    *out += name;
+    *out += " =";
+    if !value.is_empty() {
+        out.push(' ');
+        *out += value;
+    }
+    out.push('\n');
+}
+
+/// Separates the sections of a file.
+pub(crate) fn blank_line(out: &mut String, section: &mut bool) {
+    if *section {
+        out.push('\n');
+        *section = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validate::graph_differences;
+    use crate::Ninja;
+    use fs_err as fs;
+
+    // Paths and values with characters that need escaping in a manifest.
+    const SOURCE: &str = "\
+dir = $$lib
+out = ${dir}$ files
+rule cc
+  command = gcc -c $in -o $out
+  description = CC ${out}
+build $out/a$:b.o: cc src/a$$1.c | my$ header.h
+  flags = $ -O2
+build all: phony $out/a$:b.o
+default all
+";
+
+    #[test]
+    fn parse_write_parse_keeps_the_graph() {
+        let dir = std::env::temp_dir().join(format!("ninja_editor_writer_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.ninja");
+        fs::write(&path, SOURCE).unwrap();
+        let ninja = Ninja::load(&path);
+
+        let written = ninja.data().write().unwrap();
+        assert_eq!(written.len(), 1);
+        let text = &written[0].1;
+        assert!(text.contains("out = $$lib files\n"), "{text}");
+        assert!(text.contains("build $$lib$ files/a$:b.o: cc src/a$$1.c | my$ header.h\n"));
+        assert!(text.contains("  flags = $ -O2\n"));
+
+        let path = dir.join("written.ninja");
+        fs::write(&path, text).unwrap();
+        let again = Ninja::load(&path);
+        assert_eq!(graph_differences(&ninja, &again), Vec::<String>::new());
+        assert_eq!(&again.data().write().unwrap()[0].1, text);
+    }
+
+    #[test]
+    fn unescapable_characters() {
+        let dir =
+            std::env::temp_dir().join(format!("ninja_editor_writer_pipe_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.ninja");
+        fs::write(&path, "pipe = |\nbuild a$pipe: phony\n").unwrap();
+        let error = Ninja::load(&path).data().write().unwrap_err();
+        assert_eq!((error.text.as_str(), error.c), ("a|", '|'));
+    }
+}
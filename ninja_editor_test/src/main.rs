@@ -1,43 +1,50 @@
-use bumpalo::Bump;
-use ninja_editor::Ninja;
+use ninja_editor::{
+    recipe::{Recipe, RecipeError},
+    Journal, Ninja,
+};
+
+const JOURNAL: &str = "release_32/ninja_editor.journal";
+/// The recipe used without one on the command line.
+const DEFAULT_RECIPE: &str = include_str!("../rewrite.toml");
 
 fn main() {
-    let prefix = "p_";
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => rewrite(Recipe::parse(DEFAULT_RECIPE)),
+        [command, recipe] if command == "rewrite" => rewrite(Recipe::load(recipe)),
+        [command] if command == "revert" => revert(JOURNAL),
+        [command, journal] if command == "revert" => revert(journal),
+        _ => {
+            eprintln!("usage: ninja_editor_test [rewrite <recipe> | revert [journal]]");
+            std::process::exit(2);
+        }
+    }
+}
+
+fn revert(journal: &str) {
+    if let Err(e) = Journal::load(journal).and_then(|x| x.revert()) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn rewrite(recipe: Result<Recipe, RecipeError>) {
+    let recipe = recipe.unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
 
     let ninja = Ninja::load("release_32/build.ninja");
-    let data = ninja.data();
     let mut changelist = ninja.change();
-    let bump = Bump::new();
-    // for (key, rule) in data.rules.iter() {
-    //     if rule.name.elem == "phony" {
-    //         continue;
-    //     }
-    //     changelist.rename_rule(
-    //         key,
-    //         bump.alloc_str(&format!("{}{}", prefix, rule.name.elem)),
-    //     );
-    // }
-
-    for (k, v) in data.nodes.iter() {
-        if k.starts_with("cmake_") {
-            let text = bump.alloc_str(&format!("{}{}", prefix, k));
-            for loc in v {
-                changelist.change(*loc, text);
-            }
-            continue;
-        }
-        if k.starts_with("/usr/share/cmake")
-            // || k.ends_with("CMakeLists.txt")
-            // || k.ends_with(".cmake")
-            // || k.ends_with(".cmake.in")
-            // || k.ends_with("vcpkg.json")
-        {
-            let text = bump.alloc_str(&format!("{}{}", prefix, k));
-            for loc in v {
-                changelist.change(*loc, text);
-            }
-        }
+    changelist.set_validate(true);
+    changelist.set_journal(JOURNAL);
+    if let Err(e) = recipe.apply(&mut changelist) {
+        eprintln!("{e}");
+        std::process::exit(1);
     }
 
-    changelist.commit();
+    if let Err(e) = changelist.commit() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
 }